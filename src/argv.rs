@@ -0,0 +1,603 @@
+use crate::contract::{Arg, Contract, Flag, GroupKind};
+use crate::error::{Error, ErrorCode};
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+
+/// The argv of a call split according to its contract.
+#[derive(PartialEq, Debug, Default, Clone, Serialize)]
+pub struct ParsedArgs {
+    pub positional: Vec<String>,
+    /// The positional values split between the contract arguments. Keyed by argument name.
+    pub args: BTreeMap<String, Vec<String>>,
+    /// Keyed by the flag name as written in the contract, for example `--band` or `-v`.
+    pub flags: BTreeMap<String, FlagValues>,
+}
+
+#[derive(PartialEq, Debug, Default, Clone, Serialize)]
+pub struct FlagValues {
+    pub count: usize,
+    pub values: Vec<String>,
+}
+
+/// A token of an argv, before it is looked up in a contract.
+#[derive(PartialEq, Debug)]
+pub enum Token<'a> {
+    /// A positional value, `-` or anything after `--`.
+    Value(&'a str),
+    /// `--`, the end of the flags.
+    Terminator,
+    /// `--flag`, `-f` or a cluster of short flags `-abc`, with the value attached by `=`.
+    /// Negative numbers are flags too, unless a contract expects a number.
+    Flag(&'a str, Option<&'a str>),
+}
+
+/// Classifies `token` GNU style, `terminated` once `--` was given.
+pub fn token(token: &str, terminated: bool) -> Token<'_> {
+    if terminated || token == "-" || !token.starts_with('-') {
+        return Token::Value(token);
+    }
+    if token == "--" {
+        return Token::Terminator;
+    }
+
+    match token.split_once('=') {
+        Some((name, value)) => Token::Flag(name, Some(value)),
+        None => Token::Flag(token, None),
+    }
+}
+
+/// Splits `argv` GNU style and checks it against `contract`.
+///
+/// `--` ends the flags, short flags can be clustered (`-abc`), values can be attached
+/// (`-fVALUE`, `--flag=value`) or passed as the next token (`-f value`, `--flag value`).
+/// A negative number is an argument, not a flag, when it lands on a numeric argument.
+pub fn parse(argv: &[String], contract: &Contract) -> Result<ParsedArgs, Error> {
+    let contract_flags: HashMap<String, Flag> = contract
+        .flags()
+        .into_iter()
+        .map(|x| (x.name().clone(), x))
+        .collect();
+    let contract_args = contract.args();
+
+    let mut parsed = ParsedArgs::default();
+    let mut tokens = argv.iter();
+    let mut terminated = false;
+
+    while let Some(token) = tokens.next() {
+        let (name, inline_value) = match self::token(token, terminated) {
+            Token::Value(value) => {
+                parsed.positional.push(value.to_string());
+                continue;
+            }
+            Token::Terminator => {
+                terminated = true;
+                continue;
+            }
+            Token::Flag(name, value) => (name, value),
+        };
+
+        if let Some(flag) = contract_flags.get(name) {
+            let value = match (flag.required_value(), inline_value) {
+                (true, Some(value)) => Some(value.to_string()),
+                (true, None) => Some(flag_value(contract, flag, &mut tokens)?),
+                (false, Some(_)) => return Err(unexpected_value(contract, flag)),
+                (false, None) => None,
+            };
+
+            parsed.add(contract, flag, value)?;
+            continue;
+        }
+
+        let index = parsed.positional.len();
+        let count = index + 1 + values_after(tokens.as_slice(), terminated, &contract_flags);
+        let numeric_slot = slot(&contract_args, index, count).is_some_and(|x| x.kind().is_numeric());
+        if numeric_slot && token[1..].starts_with(|x: char| x.is_ascii_digit()) {
+            parsed.positional.push(token.clone());
+            continue;
+        }
+
+        if token.starts_with("--") {
+            return Err(unknown_flag(contract, name));
+        }
+
+        let cluster = &token[1..];
+        for (i, short) in cluster.char_indices() {
+            let name = format!("-{short}");
+            let Some(flag) = contract_flags.get(&name) else {
+                return Err(unknown_flag(contract, &name));
+            };
+
+            if !flag.required_value() {
+                parsed.add(contract, flag, None)?;
+                continue;
+            }
+
+            let rest = &cluster[i + short.len_utf8()..];
+            let rest = rest.strip_prefix('=').unwrap_or(rest);
+            let value = if rest.is_empty() {
+                flag_value(contract, flag, &mut tokens)?
+            } else {
+                rest.to_string()
+            };
+
+            parsed.add(contract, flag, Some(value))?;
+            break;
+        }
+    }
+
+    validate(&mut parsed, contract)?;
+
+    Ok(parsed)
+}
+
+/// The argument that the positional value at `index` lands on when `count` values are split
+/// by [`assign`].
+fn slot(args: &[Arg], index: usize, count: usize) -> Option<&Arg> {
+    let values = vec![String::new(); count.max(index + 1)];
+    let mut taken = 0;
+    for (arg, assigned) in args.iter().zip(assign(&values, args)) {
+        taken += assigned.len();
+        if index < taken {
+            return Some(arg);
+        }
+    }
+
+    None
+}
+
+/// How many of `rest` are positional values, not counting the values of flags. Negative
+/// numbers are not counted, they are only values once they land on a numeric argument.
+fn values_after(rest: &[String], mut terminated: bool, flags: &HashMap<String, Flag>) -> usize {
+    let mut count = 0;
+    let mut tokens = rest.iter();
+
+    while let Some(token) = tokens.next() {
+        match self::token(token, terminated) {
+            Token::Value(_) => count += 1,
+            Token::Terminator => terminated = true,
+            Token::Flag(name, None) if flags.get(name).is_some_and(|x| x.required_value()) => {
+                tokens.next();
+            }
+            Token::Flag(..) => {}
+        }
+    }
+
+    count
+}
+
+/// Splits `values` between `args` from left to right. Every argument takes as many values
+/// as it can while leaving enough for the required arguments after it.
+fn assign<'a>(values: &'a [String], args: &[Arg]) -> Vec<&'a [String]> {
+    let mut rest = values;
+    let mut assigned = Vec::new();
+
+    for (i, arg) in args.iter().enumerate() {
+        let reserved: usize = args[i + 1..].iter().map(|x| x.min()).sum();
+        let available = rest.len().saturating_sub(reserved);
+        let take = arg.max().map_or(available, |x| x.min(available));
+
+        assigned.push(&rest[..take]);
+        rest = &rest[take..];
+    }
+
+    assigned
+}
+
+fn arity(contract: &Contract) -> String {
+    let min = contract.min_args();
+
+    match contract.max_args() {
+        Some(max) => format!(
+            "The contract requires {} arguments and {} optional ones.",
+            min,
+            max - min
+        ),
+        None => format!(
+            "The contract requires {} arguments and any number of optional ones.",
+            min
+        ),
+    }
+}
+
+fn validate(parsed: &mut ParsedArgs, contract: &Contract) -> Result<(), Error> {
+    let count = parsed.positional.len();
+
+    if count < contract.min_args() {
+        return Err(Error::new(
+            ErrorCode::MissingArguments,
+            format!(
+                "The arguments provided are fewer than required by the contract. {}",
+                arity(contract)
+            ),
+        )
+        .contract(contract.name()));
+    }
+
+    if let Some(max) = contract.max_args()
+        && count > max
+    {
+        return Err(Error::new(
+            ErrorCode::TooManyArguments,
+            format!("Too many arguments. {}", arity(contract)),
+        )
+        .contract(contract.name())
+        .token(parsed.positional[max].clone()));
+    }
+
+    let args = contract.args();
+    for (arg, values) in args.iter().zip(assign(&parsed.positional, &args)) {
+        for value in values {
+            if !arg.kind().accepts(value) {
+                return Err(Error::new(
+                    ErrorCode::InvalidArgument,
+                    format!(
+                        "The argument \"{}\" must be of type <{}>, but \"{value}\" was provided",
+                        arg.name(),
+                        arg.kind().as_str()
+                    ),
+                )
+                .contract(contract.name())
+                .token(value.clone()));
+            }
+        }
+
+        if !values.is_empty() {
+            parsed.args.insert(arg.name().clone(), values.to_vec());
+        }
+    }
+
+    let mut flags = contract.flags().into_iter().collect::<Vec<_>>();
+    flags.sort_by(|a, b| a.name().cmp(b.name()));
+    for flag in flags {
+        if flag.is_required() && !parsed.flags.contains_key(flag.name()) {
+            return Err(Error::new(
+                ErrorCode::MissingFlag,
+                format!("One required flag is missing: {}", flag.name()),
+            )
+            .contract(contract.name())
+            .token(flag.name()));
+        }
+    }
+
+    for group in contract.groups() {
+        let given = group
+            .flags()
+            .iter()
+            .filter(|x| parsed.flags.contains_key(*x))
+            .cloned()
+            .collect::<Vec<_>>();
+
+        let message = match group.kind() {
+            GroupKind::OneOf if given.len() != 1 => format!(
+                "Exactly one of the flags {} must be given",
+                group.flags().join(", ")
+            ),
+            GroupKind::AtMostOneOf if given.len() > 1 => format!(
+                "At most one of the flags {} can be given",
+                group.flags().join(", ")
+            ),
+            GroupKind::Requires
+                if parsed.flags.contains_key(&group.flags()[0])
+                    && given.len() != group.flags().len() =>
+            {
+                format!(
+                    "The flag {} requires {}",
+                    group.flags()[0],
+                    group.flags()[1..].join(", ")
+                )
+            }
+            _ => continue,
+        };
+
+        let provided = if given.is_empty() {
+            "none was provided".to_string()
+        } else {
+            format!("{} provided", given.join(", "))
+        };
+
+        return Err(Error::new(
+            ErrorCode::FlagGroup,
+            format!("{message}, {provided} (group {group})"),
+        )
+        .contract(contract.name())
+        .token(group.to_string()));
+    }
+
+    Ok(())
+}
+
+impl ParsedArgs {
+    fn add(&mut self, contract: &Contract, flag: &Flag, value: Option<String>) -> Result<(), Error> {
+        let entry = self.flags.entry(flag.name().clone()).or_default();
+
+        if value.is_some() && entry.count > 0 && !flag.is_repeatable() {
+            return Err(Error::new(
+                ErrorCode::RepeatedFlag,
+                format!(
+                    "The flag \"{}\" can only be given once. Declare it as {}=* to accept several values",
+                    flag.name(),
+                    flag.name()
+                ),
+            )
+            .contract(contract.name())
+            .token(flag.name()));
+        }
+
+        entry.count += 1;
+        entry.values.extend(value);
+        Ok(())
+    }
+}
+
+fn flag_value<'a>(
+    contract: &Contract,
+    flag: &Flag,
+    tokens: &mut impl Iterator<Item = &'a String>,
+) -> Result<String, Error> {
+    tokens.next().cloned().ok_or_else(|| {
+        Error::new(
+            ErrorCode::MissingFlagValue,
+            format!(
+                "The flag \"{}\" must have a value, which is not provided",
+                flag.name()
+            ),
+        )
+        .contract(contract.name())
+        .token(flag.name())
+    })
+}
+
+fn unknown_flag(contract: &Contract, name: &str) -> Error {
+    Error::new(
+        ErrorCode::UnknownFlag,
+        format!("A flag was provided that is not in the contract: {name}"),
+    )
+    .contract(contract.name())
+    .token(name)
+}
+
+fn unexpected_value(contract: &Contract, flag: &Flag) -> Error {
+    Error::new(
+        ErrorCode::UnexpectedFlagValue,
+        format!("The flag \"{}\" does not take a value", flag.name()),
+    )
+    .contract(contract.name())
+    .token(flag.name())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn argv(args: &[&str]) -> Vec<String> {
+        args.iter().map(|x| x.to_string()).collect()
+    }
+
+    fn flag(count: usize, values: &[&str]) -> FlagValues {
+        FlagValues {
+            count,
+            values: argv(values),
+        }
+    }
+
+    fn code(result: Result<ParsedArgs, Error>) -> ErrorCode {
+        result.unwrap_err().code()
+    }
+
+    #[test]
+    fn positional_and_flags() {
+        let contract = "connect: ssid! password? --band=? --verbose? -v? -f=?"
+            .parse::<Contract>()
+            .unwrap();
+
+        let parsed = parse(
+            &argv(&["home", "--band", "5", "--verbose", "secret", "-f=x"]),
+            &contract,
+        )
+        .unwrap();
+
+        assert_eq!(parsed.positional, argv(&["home", "secret"]));
+        assert_eq!(parsed.flags["--band"], flag(1, &["5"]));
+        assert_eq!(parsed.flags["--verbose"], flag(1, &[]));
+        assert_eq!(parsed.flags["-f"], flag(1, &["x"]));
+
+        let parsed = parse(&argv(&["--band=2.4", "home"]), &contract).unwrap();
+        assert_eq!(parsed.flags["--band"], flag(1, &["2.4"]));
+        assert_eq!(parsed.positional, argv(&["home"]));
+    }
+
+    #[test]
+    fn tokens() {
+        assert_eq!(token("home", false), Token::Value("home"));
+        assert_eq!(token("-", false), Token::Value("-"));
+        assert_eq!(token("--", false), Token::Terminator);
+        assert_eq!(token("--", true), Token::Value("--"));
+        assert_eq!(token("--band=5", false), Token::Flag("--band", Some("5")));
+        assert_eq!(token("-abc", false), Token::Flag("-abc", None));
+        assert_eq!(token("-5", false), Token::Flag("-5", None));
+    }
+
+    #[test]
+    fn terminator() {
+        let contract = "echo: text! rest? --verbose?".parse::<Contract>().unwrap();
+
+        let parsed = parse(&argv(&["--verbose", "--", "--verbose", "-x"]), &contract).unwrap();
+        assert_eq!(parsed.positional, argv(&["--verbose", "-x"]));
+        assert_eq!(parsed.flags["--verbose"].count, 1);
+
+        let parsed = parse(&argv(&["-", "--", "--"]), &contract).unwrap();
+        assert_eq!(parsed.positional, argv(&["-", "--"]));
+    }
+
+    #[test]
+    fn short_clusters() {
+        let contract = "ls: dir? -a? -l? -v? -f=?".parse::<Contract>().unwrap();
+
+        let parsed = parse(&argv(&["-alvv", "-fVALUE", "dir"]), &contract).unwrap();
+        assert_eq!(parsed.flags["-a"].count, 1);
+        assert_eq!(parsed.flags["-l"].count, 1);
+        assert_eq!(parsed.flags["-v"].count, 2);
+        assert_eq!(parsed.flags["-f"], flag(1, &["VALUE"]));
+        assert_eq!(parsed.positional, argv(&["dir"]));
+
+        let parsed = parse(&argv(&["-af", "value"]), &contract).unwrap();
+        assert_eq!(parsed.flags["-f"], flag(1, &["value"]));
+        assert!(parsed.positional.is_empty());
+
+        let parsed = parse(&argv(&["-f", "-a"]), &contract).unwrap();
+        assert_eq!(parsed.flags["-f"], flag(1, &["-a"]));
+        assert!(!parsed.flags.contains_key("-a"));
+
+        assert_eq!(code(parse(&argv(&["-ax"]), &contract)), ErrorCode::UnknownFlag);
+        assert_eq!(code(parse(&argv(&["-f"]), &contract)), ErrorCode::MissingFlagValue);
+    }
+
+    #[test]
+    fn repeated_flags() {
+        let contract = "tag: --tag=* --verbose?".parse::<Contract>().unwrap();
+
+        let parsed = parse(
+            &argv(&["--tag", "a", "--tag=b", "--verbose", "--verbose"]),
+            &contract,
+        )
+        .unwrap();
+        assert_eq!(parsed.flags["--tag"], flag(2, &["a", "b"]));
+        assert_eq!(parsed.flags["--verbose"], flag(2, &[]));
+    }
+
+    #[test]
+    fn repeatable_flags() {
+        let contract = "tag: --tag=* --label=?".parse::<Contract>().unwrap();
+        assert_eq!(
+            code(parse(&argv(&["--label", "a", "--label=b"]), &contract)),
+            ErrorCode::RepeatedFlag
+        );
+
+        let contract = "tag: --tag=+".parse::<Contract>().unwrap();
+        assert_eq!(code(parse(&argv(&[]), &contract)), ErrorCode::MissingFlag);
+    }
+
+    #[test]
+    fn flag_groups() {
+        let contract = "connect: --wifi? --ethernet? --ssid=? --password=? --json? --csv? one_of{--wifi,--ethernet} requires{--password,--ssid} at_most_one_of{--json,--csv}"
+            .parse::<Contract>()
+            .unwrap();
+
+        assert!(parse(&argv(&["--wifi", "--ssid", "home", "--password=x"]), &contract).is_ok());
+        assert!(parse(&argv(&["--ethernet", "--csv"]), &contract).is_ok());
+
+        let err = parse(&argv(&[]), &contract).unwrap_err();
+        assert_eq!(err.code(), ErrorCode::FlagGroup);
+        assert_eq!(
+            err.to_string(),
+            "Exactly one of the flags --wifi, --ethernet must be given, none was provided (group one_of{--wifi,--ethernet})"
+        );
+
+        assert_eq!(
+            code(parse(&argv(&["--wifi", "--ethernet"]), &contract)),
+            ErrorCode::FlagGroup
+        );
+        assert_eq!(
+            code(parse(&argv(&["--wifi", "--password", "x"]), &contract)),
+            ErrorCode::FlagGroup
+        );
+        assert_eq!(
+            code(parse(&argv(&["--wifi", "--json", "--csv"]), &contract)),
+            ErrorCode::FlagGroup
+        );
+    }
+
+    #[test]
+    fn variadic_args() {
+        let contract = "tag: tag! files...!".parse::<Contract>().unwrap();
+
+        let parsed = parse(&argv(&["red", "a", "b", "c"]), &contract).unwrap();
+        assert_eq!(parsed.args["tag"], argv(&["red"]));
+        assert_eq!(parsed.args["files"], argv(&["a", "b", "c"]));
+        assert_eq!(code(parse(&argv(&["red"]), &contract)), ErrorCode::MissingArguments);
+
+        let contract = "cp: sources...! target!".parse::<Contract>().unwrap();
+        let parsed = parse(&argv(&["a", "b", "dir"]), &contract).unwrap();
+        assert_eq!(parsed.args["sources"], argv(&["a", "b"]));
+        assert_eq!(parsed.args["target"], argv(&["dir"]));
+
+        let contract = "opt: first? second!".parse::<Contract>().unwrap();
+        let parsed = parse(&argv(&["x"]), &contract).unwrap();
+        assert_eq!(parsed.args.get("first"), None);
+        assert_eq!(parsed.args["second"], argv(&["x"]));
+
+        let contract = "range: points<int>{2,3} rest...?".parse::<Contract>().unwrap();
+        assert_eq!(code(parse(&argv(&["1"]), &contract)), ErrorCode::MissingArguments);
+        let parsed = parse(&argv(&["1", "-2", "-3", "4"]), &contract).unwrap();
+        assert_eq!(parsed.args["points"], argv(&["1", "-2", "-3"]));
+        assert_eq!(parsed.args["rest"], argv(&["4"]));
+
+        let contract = "range: points<int>{2,3}".parse::<Contract>().unwrap();
+        assert_eq!(
+            code(parse(&argv(&["1", "2", "3", "4"]), &contract)),
+            ErrorCode::TooManyArguments
+        );
+    }
+
+    #[test]
+    fn negative_number_reserved() {
+        let contract = "opt: first? second<int>!".parse::<Contract>().unwrap();
+
+        let parsed = parse(&argv(&["-5"]), &contract).unwrap();
+        assert_eq!(parsed.args.get("first"), None);
+        assert_eq!(parsed.args["second"], argv(&["-5"]));
+
+        let parsed = parse(&argv(&["x", "-5"]), &contract).unwrap();
+        assert_eq!(parsed.args["first"], argv(&["x"]));
+        assert_eq!(parsed.args["second"], argv(&["-5"]));
+
+        let contract = "opt: first? second<int>! --band=?".parse::<Contract>().unwrap();
+        let parsed = parse(&argv(&["-5", "--band", "5"]), &contract).unwrap();
+        assert_eq!(parsed.args["second"], argv(&["-5"]));
+        assert_eq!(code(parse(&argv(&["-5", "3"]), &contract)), ErrorCode::UnknownFlag);
+    }
+
+    #[test]
+    fn negative_numbers() {
+        let contract = "move: dx<int>! dy<num>!".parse::<Contract>().unwrap();
+
+        let parsed = parse(&argv(&["-5", "-2.5"]), &contract).unwrap();
+        assert_eq!(parsed.positional, argv(&["-5", "-2.5"]));
+
+        let contract = "move: name! dx<int>!".parse::<Contract>().unwrap();
+        assert_eq!(code(parse(&argv(&["-5", "3"]), &contract)), ErrorCode::UnknownFlag);
+        assert!(parse(&argv(&["x", "-5"]), &contract).is_ok());
+        assert_eq!(
+            code(parse(&argv(&["x", "five"]), &contract)),
+            ErrorCode::InvalidArgument
+        );
+    }
+
+    #[test]
+    fn contract_violations() {
+        let contract = "connect: ssid! password? --ssid! --band=? --quiet?"
+            .parse::<Contract>()
+            .unwrap();
+
+        assert_eq!(code(parse(&argv(&["--ssid"]), &contract)), ErrorCode::MissingArguments);
+        assert_eq!(
+            code(parse(&argv(&["a", "b", "c", "--ssid"]), &contract)),
+            ErrorCode::TooManyArguments
+        );
+        assert_eq!(code(parse(&argv(&["a"]), &contract)), ErrorCode::MissingFlag);
+        assert_eq!(
+            code(parse(&argv(&["a", "--ssid", "--nope"]), &contract)),
+            ErrorCode::UnknownFlag
+        );
+        assert_eq!(
+            code(parse(&argv(&["a", "--ssid", "--quiet=yes"]), &contract)),
+            ErrorCode::UnexpectedFlagValue
+        );
+
+        // A flag that is missing its value no longer hides the flags after it.
+        assert_eq!(
+            code(parse(&argv(&["a", "--ssid", "--band", "--nope", "--x"]), &contract)),
+            ErrorCode::UnknownFlag
+        );
+    }
+}
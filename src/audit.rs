@@ -0,0 +1,96 @@
+use crate::config::audit_log;
+use crate::method::Method;
+use serde::{Deserialize, Serialize};
+use std::env;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// The record of the current call. It is only kept when the audit log is enabled.
+static PENDING: Mutex<Option<Record>> = Mutex::new(None);
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Record {
+    pub timestamp: u64,
+    pub method: String,
+    pub path: PathBuf,
+    pub args: Vec<String>,
+    pub cwd: Option<PathBuf>,
+    pub stdin_piped: bool,
+    pub stdout_piped: bool,
+    pub validation: Validation,
+    pub exit_code: Option<i32>,
+    pub duration_ms: Option<u64>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Validation {
+    Passed,
+    Rejected,
+}
+
+/// Starts the record of a call. Until [`passed`] is called it is considered rejected.
+pub fn begin(method: &Method) {
+    if audit_log().is_none() {
+        return;
+    }
+
+    let record = Record {
+        timestamp: now(),
+        method: method.dotted_name.clone(),
+        path: method.path.clone(),
+        args: method.args.clone(),
+        cwd: env::current_dir().ok(),
+        stdin_piped: method.stdin.stream().is_provided(),
+        stdout_piped: method.stdout.is_pipeline(),
+        validation: Validation::Rejected,
+        exit_code: None,
+        duration_ms: None,
+    };
+
+    *PENDING.lock().unwrap() = Some(record);
+}
+
+/// Marks the call as validated. The path is taken again, since interfaces replace it.
+pub fn passed(method: &Method) {
+    if let Some(record) = PENDING.lock().unwrap().as_mut() {
+        record.path = method.path.clone();
+        record.validation = Validation::Passed;
+    }
+}
+
+/// Appends the pending record to the log.
+pub fn finish(exit_code: Option<i32>, duration: Option<Duration>) {
+    let Some(mut record) = PENDING.lock().unwrap().take() else {
+        return;
+    };
+    let Some(path) = audit_log() else {
+        return;
+    };
+
+    record.exit_code = exit_code;
+    record.duration_ms = duration.map(|x| x.as_millis() as u64);
+
+    let mut line = serde_json::to_string(&record).unwrap();
+    line.push('\n');
+
+    let result = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&path)
+        .and_then(|mut file| file.write_all(line.as_bytes()));
+
+    if let Err(err) = result {
+        eprintln!("Unable to write the audit log {:?}: {err}", path);
+    }
+}
+
+pub fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
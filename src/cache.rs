@@ -0,0 +1,209 @@
+use crate::argv::ParsedArgs;
+use crate::audit::now;
+use serde::{Deserialize, Serialize};
+use std::fs::{self, File};
+use std::io;
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+/// The cached result of a call to a `@pure` method.
+///
+/// Results are stored in a directory per method, named by its dotted name: the stdout
+/// in `<hash>.out` and the exit code with the full key in `<hash>.json`.
+pub struct Cache {
+    dir: PathBuf,
+    hash: String,
+    key: String,
+    ttl: Option<Duration>,
+    /// Suffix of the files written before they are renamed into place, unique to the
+    /// call so that concurrent calls with the same key do not write to the same file.
+    temporary: String,
+}
+
+static TEMPORARIES: AtomicU64 = AtomicU64::new(0);
+
+#[derive(Serialize, Deserialize)]
+struct Entry {
+    key: String,
+    created: u64,
+    exit_code: i32,
+}
+
+#[derive(Serialize)]
+struct Key<'a> {
+    method: &'a str,
+    args: Option<&'a ParsedArgs>,
+    stdin: String,
+    mtime: i64,
+    file: String,
+}
+
+impl Cache {
+    /// The key covers the dotted name, the parsed argv, stdin and the method file,
+    /// so editing the method invalidates its results.
+    pub fn new(
+        root: &Path,
+        method: &str,
+        path: &Path,
+        args: Option<&ParsedArgs>,
+        stdin: &[u8],
+        ttl: Option<Duration>,
+    ) -> io::Result<Cache> {
+        let key = serde_json::to_string(&Key {
+            method,
+            args,
+            stdin: hash(stdin),
+            mtime: fs::metadata(path)?.mtime(),
+            file: hash(&fs::read(path)?),
+        })?;
+
+        Ok(Cache {
+            dir: root.join(method),
+            hash: hash(key.as_bytes()),
+            key,
+            ttl,
+            temporary: format!(
+                "{}.{}.tmp",
+                std::process::id(),
+                TEMPORARIES.fetch_add(1, Ordering::Relaxed)
+            ),
+        })
+    }
+
+    /// The exit code and stdout of a previous call, if they are still valid.
+    pub fn lookup(&self) -> Option<(i32, Vec<u8>)> {
+        let entry = fs::read_to_string(self.file("json", false)).ok()?;
+        let entry = serde_json::from_str::<Entry>(&entry).ok()?;
+
+        if entry.key != self.key
+            || self
+                .ttl
+                .is_some_and(|x| now().saturating_sub(entry.created) > x.as_secs())
+        {
+            return None;
+        }
+
+        let stdout = fs::read(self.file("out", false)).ok()?;
+        Some((entry.exit_code, stdout))
+    }
+
+    /// A file that receives the stdout of the call until [`Cache::store`] keeps it.
+    pub fn sink(&self) -> io::Result<File> {
+        fs::create_dir_all(&self.dir)?;
+        File::create(self.file("out", true))
+    }
+
+    pub fn store(&self, exit_code: i32) -> io::Result<()> {
+        fs::rename(self.file("out", true), self.file("out", false))?;
+
+        let entry = Entry {
+            key: self.key.clone(),
+            created: now(),
+            exit_code,
+        };
+        fs::write(self.file("json", true), serde_json::to_string(&entry)?)?;
+        fs::rename(self.file("json", true), self.file("json", false))
+    }
+
+    /// Drops the captured stdout of a call whose result is not kept.
+    pub fn discard(&self) {
+        let _ = fs::remove_file(self.file("out", true));
+    }
+
+    /// `<hash>.<extension>`, or the temporary file of this call that becomes it.
+    fn file(&self, extension: &str, temporary: bool) -> PathBuf {
+        match temporary {
+            true => self.dir.join(format!("{}.{extension}.{}", self.hash, self.temporary)),
+            false => self.dir.join(format!("{}.{extension}", self.hash)),
+        }
+    }
+}
+
+/// Removes the results of every method whose dotted name is `prefix` or starts with `prefix.`.
+/// Returns the number of methods whose results were removed.
+pub fn clear(root: &Path, prefix: Option<&str>) -> io::Result<usize> {
+    let entries = match fs::read_dir(root) {
+        Ok(entries) => entries,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(0),
+        Err(err) => return Err(err),
+    };
+
+    let mut removed = 0;
+    for entry in entries {
+        let entry = entry?;
+        let name = entry.file_name().to_string_lossy().to_string();
+
+        if prefix.is_none_or(|x| name == x || name.starts_with(&format!("{x}."))) {
+            fs::remove_dir_all(entry.path())?;
+            removed += 1;
+        }
+    }
+
+    Ok(removed)
+}
+
+/// 64-bit FNV-1a, stable across builds unlike the hasher of the standard library.
+pub(crate) fn hash(data: &[u8]) -> String {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for byte in data {
+        hash ^= *byte as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+
+    format!("{hash:016x}")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::temp_dir::TempDir;
+    use std::io::Write;
+
+    #[test]
+    fn fnv() {
+        assert_eq!(hash(b""), "cbf29ce484222325");
+        assert_eq!(hash(b"a"), "af63dc4c8601ec8c");
+    }
+
+    #[test]
+    fn store_and_clear() {
+        let root = TempDir::new("cache");
+        let method = root.join("info");
+        fs::write(&method, "#!/bin/sh\necho info\n").unwrap();
+
+        let cache = Cache::new(&root.join("results"), "sys.hw.info", &method, None, b"", None).unwrap();
+        assert_eq!(cache.lookup(), None);
+
+        cache.sink().unwrap().write_all(b"info\n").unwrap();
+        cache.store(0).unwrap();
+        assert_eq!(cache.lookup(), Some((0, b"info\n".to_vec())));
+
+        let other = Cache::new(&root.join("results"), "sys.hw.info", &method, None, b"x", None).unwrap();
+        assert_eq!(other.lookup(), None);
+
+        assert_eq!(clear(&root.join("results"), Some("sys.hw2")).unwrap(), 0);
+        assert_eq!(clear(&root.join("results"), Some("sys")).unwrap(), 1);
+        assert_eq!(cache.lookup(), None);
+        assert_eq!(clear(&root.join("missing"), None).unwrap(), 0);
+    }
+
+    #[test]
+    fn concurrent_calls() {
+        let root = TempDir::new("cache-concurrent");
+        let method = root.join("info");
+        fs::write(&method, "#!/bin/sh\necho info\n").unwrap();
+
+        let first = Cache::new(&root.join("results"), "sys.hw.info", &method, None, b"", None).unwrap();
+        let second = Cache::new(&root.join("results"), "sys.hw.info", &method, None, b"", None).unwrap();
+        let mut sink = first.sink().unwrap();
+        second.sink().unwrap().write_all(b"second\n").unwrap();
+        sink.write_all(b"first\n").unwrap();
+
+        second.discard();
+        first.store(0).unwrap();
+        assert_eq!(second.lookup(), Some((0, b"first\n".to_vec())));
+        assert_eq!(fs::read_dir(root.join("results/sys.hw.info")).unwrap().count(), 2);
+    }
+}
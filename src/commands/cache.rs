@@ -0,0 +1,12 @@
+use crate::cache;
+use crate::config::cache_dir;
+use crate::error::Error;
+
+pub fn clear(prefix: Option<String>) {
+    let root = cache_dir().join("results");
+
+    match cache::clear(&root, prefix.as_deref()) {
+        Ok(count) => println!("Cleared cached results of {count} methods"),
+        Err(err) => Error::io(&root, err).exit(),
+    }
+}
@@ -0,0 +1,88 @@
+use crate::commands::methods;
+use crate::error::{Error, ErrorCode};
+use crate::handlers::{Handle, ResolveHandler, object_contracts};
+use crate::header;
+use crate::method::Method;
+use crate::strictness::Strictness;
+use std::path::PathBuf;
+
+/// Resolves every method under `prefix` and reports the ones without a contract.
+///
+/// Exits with 1 if a method cannot be resolved, if the contract in the header of a method
+/// file disagrees with `.self`, or if a method without a contract is under the `strict`
+/// policy and would be refused.
+pub fn execute(namespace: PathBuf, prefix: Option<String>, cached: bool) {
+    let methods = match methods::list(&namespace, cached) {
+        Ok(methods) => methods,
+        Err(err) => err.exit(),
+    };
+
+    let (mut total, mut contracted, mut failed) = (0, 0, false);
+    for name in methods.into_iter().filter(|x| {
+        prefix
+            .as_ref()
+            .is_none_or(|prefix| x == prefix || x.starts_with(&format!("{prefix}.")))
+    }) {
+        total += 1;
+
+        // Resolved as if called from its own object, private methods are not a failure.
+        let resolved = Method::new(name.clone(), vec![], namespace.clone()).and_then(|mut method| {
+            method.caller = Some(name.clone());
+            ResolveHandler::handle(&mut method).map(|_| method)
+        });
+        let method = match resolved {
+            Ok(method) => method,
+            Err(err) => {
+                println!("{name}: {err}");
+                failed = true;
+                continue;
+            }
+        };
+
+        if let Some(problem) = disagreement(&method) {
+            println!("{name}: {problem}");
+            failed = true;
+        }
+
+        if method.contract().is_some() {
+            contracted += 1;
+            continue;
+        }
+
+        let strictness = method.strictness();
+        println!("{name}: no contract ({})", strictness.as_str());
+        failed |= strictness == Strictness::Strict;
+    }
+
+    let percent = match total {
+        0 => 100,
+        _ => contracted * 100 / total,
+    };
+    println!("{contracted} of {total} methods have a contract ({percent}%)");
+
+    if failed {
+        Error::new(ErrorCode::CheckFailed, "Some methods failed the check").exit();
+    }
+}
+
+/// How the `#>>>` header of the method file conflicts with `.self`, if it does.
+fn disagreement(method: &Method) -> Option<String> {
+    let header = match header::contract(&method.path, &method.name) {
+        Ok(header) => header?,
+        Err(err) => return Some(format!("the contract in the file header is invalid: {err}")),
+    };
+
+    if header.name() != &method.name {
+        return Some(format!(
+            "the contract in the file header is named \"{}\"",
+            header.name()
+        ));
+    }
+
+    let declared = object_contracts(&method.object.path)
+        .ok()
+        .flatten()
+        .and_then(|mut x| x.remove(&method.name))?;
+
+    (declared != header).then(|| "the contract in the file header disagrees with .self, which wins".into())
+}
@@ -1,7 +1,14 @@
-use std::path::PathBuf;
-use clap::Subcommand;
+use crate::commands::log::{Status, parse_time};
+use crate::commands::test::ReportFormat;
+use crate::lock::{LockWait, parse_wait};
+use crate::mock;
+use crate::supervisor::{Supervision, parse_duration, parse_size};
+use clap::{Args, Subcommand};
 use clap_complete::Shell;
+use std::path::PathBuf;
+use std::time::Duration;
 
+#[allow(clippy::large_enum_variant)]
 #[derive(Subcommand)]
 pub enum Commands {
     #[command(
@@ -10,14 +17,54 @@ pub enum Commands {
         help_expected = true
     )]
     Execute {
-        #[arg(help = "Path to method. For example `object.method`")]
+        #[arg(help = "Path to method. For example `object.method`, or `host:object.method` to run it over ssh")]
         method: String,
 
-        #[arg(trailing_var_arg = true)]
+        #[arg(trailing_var_arg = true, help = "Arguments passed to the method")]
         args: Vec<String>,
 
         #[arg(short, long, help = "Directory path")]
         namespace: Option<PathBuf>,
+
+        #[arg(
+            long,
+            help = "Do not check stdin against the contract, for callers such as cron that leave it open"
+        )]
+        no_stdin_check: bool,
+
+        #[arg(long, help = "Refuse deprecated and uncontracted methods instead of warning")]
+        strict: bool,
+
+        #[arg(
+            long,
+            value_name = "PATH",
+            help = "Read stdin of the method from a file, for example one saved with --record-stdin"
+        )]
+        stdin_file: Option<PathBuf>,
+
+        #[arg(
+            long,
+            value_name = "WAIT",
+            value_parser = parse_wait,
+            help = "How to wait for a @lock held by another call: `wait`, `fail` or a timeout such as `30s`"
+        )]
+        lock_wait: Option<LockWait>,
+
+        #[arg(long, value_name = "HOST", help = "Run the method on a host over ssh")]
+        remote: Option<String>,
+
+        #[arg(
+            long,
+            value_name = "PATH",
+            help = "Namespace on the remote host. Defaults to IRNIX_REMOTE_NAMESPACE or the local namespace"
+        )]
+        remote_namespace: Option<PathBuf>,
+
+        #[command(flatten)]
+        supervision: SupervisionArgs,
+
+        #[arg(long, value_name = "PATH", hide = true, help = "Mocks of the `irnix test` run making the call")]
+        mocks: Option<PathBuf>,
     },
     #[command(about = "Generate auto completion", help_expected = true)]
     Completions {
@@ -29,4 +76,276 @@ pub enum Commands {
         #[arg(short, long, help = "Directory path")]
         namespace: Option<PathBuf>,
     },
-}
\ No newline at end of file
+    #[command(
+        about = "Reports methods without a contract and methods that cannot be resolved",
+        help_expected = true
+    )]
+    Check {
+        #[arg(help = "Method or object prefix. For example `net.wifi`")]
+        prefix: Option<String>,
+
+        #[arg(short, long, help = "Directory path")]
+        namespace: Option<PathBuf>,
+    },
+    #[command(
+        about = "Runs the example calls declared in the .tests of objects",
+        help_expected = true
+    )]
+    Test {
+        #[arg(help = "Method or object prefix. For example `net.wifi`")]
+        prefix: Option<String>,
+
+        #[arg(short, long, help = "Directory path")]
+        namespace: Option<PathBuf>,
+
+        #[arg(long, value_enum, default_value_t, help = "Format of the report")]
+        format: ReportFormat,
+
+        #[arg(
+            long = "mock",
+            value_name = "METHOD=SCRIPT",
+            value_parser = mock::parse,
+            help = "Replace a method with a script in every call of the cases. For example `net.wifi.connect=fixtures/connect.sh`"
+        )]
+        mocks: Vec<(String, PathBuf)>,
+
+        #[arg(
+            long,
+            value_name = "PATH",
+            help = "Directory laid out like the namespace whose methods replace the ones of the namespace"
+        )]
+        mock_namespace: Option<PathBuf>,
+    },
+    #[command(
+        about = "Proposes a contract for a method from a static read of its script",
+        help_expected = true
+    )]
+    Infer {
+        #[arg(help = "Path to method. For example `object.method`")]
+        method: String,
+
+        #[arg(short, long, help = "Directory path")]
+        namespace: Option<PathBuf>,
+
+        #[arg(long, help = "Append the contract to the .self of the object")]
+        append: bool,
+    },
+    #[command(
+        about = "Calls a method with generated valid and invalid argvs and checks how it answers",
+        help_expected = true
+    )]
+    Fuzz {
+        #[arg(help = "Path to method. For example `object.method`")]
+        method: String,
+
+        #[arg(long, help = "Directory path")]
+        namespace: Option<PathBuf>,
+
+        #[arg(short = 'n', long, default_value_t = 100, help = "Number of calls to generate")]
+        count: usize,
+
+        #[arg(long, help = "Seed of the first call. Defaults to one drawn from the clock")]
+        seed: Option<u64>,
+
+        #[arg(long, help = "Generate the saved failing calls again instead of new ones")]
+        replay: bool,
+
+        #[arg(long, help = "Only check the calls against the contract, do not run the method")]
+        no_exec: bool,
+
+        #[arg(
+            long,
+            value_name = "DURATION",
+            value_parser = parse_duration,
+            default_value = "10s",
+            help = "Wall-clock timeout of every call"
+        )]
+        timeout: Duration,
+    },
+    #[command(about = "Query the audit log of method calls", help_expected = true)]
+    Log {
+        #[arg(short, long, help = "Method or object prefix. For example `net.wifi`")]
+        method: Option<String>,
+
+        #[arg(
+            long,
+            value_parser = parse_time,
+            help = "Unix timestamp or a duration back from now, for example `2h`"
+        )]
+        since: Option<u64>,
+
+        #[arg(
+            long,
+            value_parser = parse_time,
+            help = "Unix timestamp or a duration back from now, for example `30m`"
+        )]
+        until: Option<u64>,
+
+        #[arg(short, long, help = "Outcome of the call")]
+        status: Option<Status>,
+
+        #[arg(long, help = "Print the matching records as JSON lines")]
+        json: bool,
+
+        #[arg(short, long, help = "Log path. Defaults to IRNIX_AUDIT_LOG")]
+        file: Option<PathBuf>,
+    },
+    #[command(
+        about = "Serve a JSON-RPC API on a Unix socket and keep the namespace index warm",
+        help_expected = true
+    )]
+    Daemon {
+        #[arg(short, long, help = "Directory path")]
+        namespace: Option<PathBuf>,
+
+        #[arg(long, help = "Socket path. Defaults to $XDG_RUNTIME_DIR/irnix/daemon.sock")]
+        socket: Option<PathBuf>,
+    },
+    #[command(about = "List the method locks held by running calls", help_expected = true)]
+    Locks,
+    #[command(about = "Manage cached results of pure methods", help_expected = true)]
+    Cache {
+        #[command(subcommand)]
+        command: CacheCommands,
+    },
+    #[command(about = "Inspect or reset the state of objects", help_expected = true)]
+    State {
+        #[command(subcommand)]
+        command: StateCommands,
+    },
+    #[command(
+        about = "Observe the calls of uncontracted methods and suggest contracts for them",
+        help_expected = true
+    )]
+    Learn {
+        #[command(subcommand)]
+        command: LearnCommands,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum CacheCommands {
+    #[command(about = "Remove cached results of pure methods", help_expected = true)]
+    Clear {
+        #[arg(help = "Method or object prefix. For example `sys.hw`. Clears everything if omitted")]
+        prefix: Option<String>,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum StateCommands {
+    #[command(about = "List the state of an object and of its instances", help_expected = true)]
+    Show {
+        #[arg(help = "Dotted object, optionally with an instance. For example `counter#a`")]
+        object: String,
+    },
+    #[command(about = "Remove the state of an object and of its instances, or of one instance", help_expected = true)]
+    Reset {
+        #[arg(help = "Dotted object, optionally with an instance. For example `counter#a`")]
+        object: String,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum LearnCommands {
+    #[command(about = "Start observing the calls of uncontracted methods", help_expected = true)]
+    Start {
+        #[arg(help = "Method or object prefix. For example `net.wifi`")]
+        prefix: Option<String>,
+
+        #[arg(
+            long = "for",
+            value_name = "DURATION",
+            value_parser = parse_duration,
+            help = "Stop observing after a while, for example `1d`"
+        )]
+        duration: Option<Duration>,
+    },
+    #[command(about = "Stop observing calls, the observations are kept", help_expected = true)]
+    Stop,
+    #[command(
+        about = "Print the tightest contract consistent with the observed calls of a method",
+        help_expected = true
+    )]
+    Suggest {
+        #[arg(help = "Path to method. For example `object.method`")]
+        method: String,
+    },
+}
+
+#[derive(Args)]
+pub struct SupervisionArgs {
+    #[arg(
+        long,
+        help = "Run the method as a child of irnix instead of replacing irnix with it"
+    )]
+    supervised: bool,
+
+    #[arg(
+        long,
+        value_name = "DURATION",
+        value_parser = parse_duration,
+        help = "Wall-clock timeout, for example `30s` or `5m`. Implies --supervised"
+    )]
+    timeout: Option<Duration>,
+
+    #[arg(
+        long,
+        value_name = "DURATION",
+        value_parser = parse_duration,
+        help = "CPU time limit. Implies --supervised"
+    )]
+    cpu_limit: Option<Duration>,
+
+    #[arg(
+        long,
+        value_name = "SIZE",
+        value_parser = parse_size,
+        help = "Address space limit, for example `512M`. Implies --supervised"
+    )]
+    memory_limit: Option<u64>,
+
+    #[arg(
+        long,
+        value_name = "COUNT",
+        help = "Limit of open file descriptors. Implies --supervised"
+    )]
+    files_limit: Option<u64>,
+
+    #[arg(long, help = "Working directory of the method. Implies --supervised")]
+    cwd: Option<PathBuf>,
+
+    #[arg(
+        long,
+        value_name = "PATH",
+        help = "Append stdout of the method to a file as well. Implies --supervised"
+    )]
+    tee_stdout: Option<PathBuf>,
+
+    #[arg(
+        long,
+        value_name = "PATH",
+        help = "Save stdin of the method to a file. Implies --supervised"
+    )]
+    record_stdin: Option<PathBuf>,
+}
+
+impl From<SupervisionArgs> for Option<Supervision> {
+    fn from(args: SupervisionArgs) -> Self {
+        let supervision = Supervision {
+            timeout: args.timeout,
+            cpu: args.cpu_limit,
+            memory: args.memory_limit,
+            files: args.files_limit,
+            cwd: args.cwd,
+            tee: args.tee_stdout,
+            record: args.record_stdin,
+        };
+
+        if args.supervised || supervision != Supervision::default() {
+            Some(supervision)
+        } else {
+            None
+        }
+    }
+}
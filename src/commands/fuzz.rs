@@ -0,0 +1,184 @@
+use crate::commands::test::{self, Fixture};
+use crate::config::state_dir;
+use crate::contract::Contract;
+use crate::error::{Error, ErrorCode};
+use crate::fuzz::{self, Rng};
+use crate::handlers::{Handle, ResolveHandler, ValidationHandler};
+use crate::index;
+use crate::method::Method;
+use crate::mock::Mocks;
+use std::fs;
+use std::io::{self, ErrorKind};
+use std::path::{self, Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+pub struct Options {
+    /// Number of calls to generate.
+    pub count: usize,
+    /// Seed of the first call, the next calls use the following seeds.
+    pub seed: Option<u64>,
+    /// Generate the saved failing seeds instead of new ones.
+    pub replay: bool,
+    /// Run the valid calls, not only check them against the contract.
+    pub run: bool,
+    pub timeout: Duration,
+}
+
+/// Generates valid and invalid calls of `method` from its contract. Invalid calls must be
+/// refused by the contract, valid ones accepted and, when run, exit with 0 or a declared code.
+///
+/// The seeds of failing calls are saved, `--replay` generates them again and forgets the
+/// ones that pass.
+pub fn execute(namespace: PathBuf, method: String, options: Options, cached: bool) {
+    let namespace = path::absolute(&namespace).unwrap_or(namespace);
+    if cached {
+        index::set(index::open(&namespace));
+    }
+
+    let contract = match contract(&namespace, &method) {
+        Ok(contract) => contract,
+        Err(err) => err.exit(),
+    };
+
+    let file = state_dir().join("fuzz").join(format!("{method}.seeds"));
+    let seeds = match options.replay {
+        true => match saved(&file) {
+            Ok(seeds) => seeds,
+            Err(err) => Error::io(&file, err).exit(),
+        },
+        false => {
+            let seed = options.seed.unwrap_or_else(|| {
+                SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .map_or(0, |x| x.as_nanos() as u64)
+            });
+            println!("Seed {seed}");
+            (0..options.count as u64).map(|x| seed.wrapping_add(x)).collect()
+        }
+    };
+
+    let mut failures = Vec::new();
+    for &seed in &seeds {
+        if let Err(message) = check(&namespace, &method, &contract, seed, &options, cached) {
+            println!("seed {seed}: {message}");
+            failures.push((seed, message));
+        }
+    }
+
+    if let Err(err) = save(&file, &failures, !options.replay) {
+        Error::io(&file, err).exit();
+    }
+
+    println!("{} calls, {} failed", seeds.len(), failures.len());
+    if !failures.is_empty() {
+        Error::new(
+            ErrorCode::FuzzFailed,
+            format!(
+                "The failing seeds are saved to {}, replay them with `irnix fuzz {method} --replay`",
+                file.display()
+            ),
+        )
+        .token(method)
+        .exit();
+    }
+}
+
+fn contract(namespace: &Path, method: &str) -> Result<Contract, Error> {
+    let mut resolved = Method::new(method.to_string(), vec![], namespace.to_path_buf())?;
+    ResolveHandler::handle(&mut resolved)?;
+
+    resolved.contract().cloned().ok_or_else(|| {
+        Error::new(ErrorCode::Uncontracted, "The method has no contract to generate calls from")
+            .token(method)
+    })
+}
+
+/// Generates the call of `seed`. Whether it is valid is drawn from the seed too, so that
+/// the seed alone replays it.
+fn check(
+    namespace: &Path,
+    method: &str,
+    contract: &Contract,
+    seed: u64,
+    options: &Options,
+    cached: bool,
+) -> Result<(), String> {
+    let mut rng = Rng::new(seed);
+    let valid = rng.chance();
+    let call = fuzz::generate(contract, &mut rng, valid);
+    let argv = &call.argv;
+
+    let mut validated = Method::new(method.to_string(), argv.clone(), namespace.to_path_buf())
+        .map_err(|x| x.to_string())?;
+
+    match (ValidationHandler::handle(&mut validated), call.mutation) {
+        (Ok(()), Some(mutation)) => return Err(format!("{argv:?} with {mutation} was accepted")),
+        (Err(_), Some(_)) => return Ok(()),
+        (Err(err), None) => return Err(format!("{argv:?} was refused: {err}")),
+        (Ok(()), None) if !options.run => return Ok(()),
+        (Ok(()), None) => {}
+    }
+
+    let stdin = match &call.stdin {
+        Some(text) => Fixture::Text(text),
+        None => Fixture::Null,
+    };
+    let timeout = ["--timeout".to_string(), format!("{}s", options.timeout.as_secs())];
+
+    let run = test::call(namespace, method, argv, stdin, &timeout, &Mocks::default(), cached)
+        .map_err(|x| x.to_string())?;
+    let exit_code = run.exit_code;
+
+    if exit_code == 0 || contract.error_codes().contains(&(exit_code as u32)) {
+        return Ok(());
+    }
+
+    let message = format!("{argv:?} exited with {exit_code}, which the contract does not declare");
+    match run.stderr.trim() {
+        "" => Err(message),
+        stderr => Err(format!("{message}: {stderr}")),
+    }
+}
+
+/// The seeds saved by earlier runs, one per line before a tab and the failure.
+fn saved(file: &Path) -> io::Result<Vec<u64>> {
+    match fs::read_to_string(file) {
+        Ok(text) => Ok(text
+            .lines()
+            .filter_map(|x| x.split('\t').next()?.parse().ok())
+            .collect()),
+        Err(err) if err.kind() == ErrorKind::NotFound => Ok(vec![]),
+        Err(err) => Err(err),
+    }
+}
+
+/// Appends the failures to the saved seeds, or replaces them, skipping seeds already saved.
+fn save(file: &Path, failures: &[(u64, String)], append: bool) -> io::Result<()> {
+    let mut seeds = match append {
+        true => saved(file)?,
+        false => vec![],
+    };
+    let mut text = match append {
+        true => fs::read_to_string(file).unwrap_or_default(),
+        false => String::new(),
+    };
+
+    for (seed, message) in failures {
+        if !seeds.contains(seed) {
+            seeds.push(*seed);
+            text += &format!("{seed}\t{}\n", message.replace(['\n', '\t'], " "));
+        }
+    }
+
+    if text.is_empty() {
+        return match fs::remove_file(file) {
+            Err(err) if err.kind() != ErrorKind::NotFound => Err(err),
+            _ => Ok(()),
+        };
+    }
+
+    if let Some(dir) = file.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    fs::write(file, text)
+}
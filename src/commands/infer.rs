@@ -0,0 +1,73 @@
+use crate::contract::Contract;
+use crate::error::{Error, ErrorCode};
+use crate::handlers::object_contracts;
+use crate::header;
+use crate::infer;
+use crate::method::Method;
+use std::fs::{self, OpenOptions};
+use std::io::{ErrorKind, Write};
+use std::path::PathBuf;
+
+/// Prints a draft contract read from the script of the method. With `append` it is also
+/// added to the `.self` of the object, unless the method already has a contract.
+pub fn execute(namespace: PathBuf, method: String, append: bool) {
+    let method = match Method::new(method, vec![], namespace) {
+        Ok(method) => method,
+        Err(err) => err.exit(),
+    };
+
+    let script = match fs::read(&method.path) {
+        Ok(script) => String::from_utf8_lossy(&script).to_string(),
+        Err(err) => Error::io(&method.path, err).exit(),
+    };
+
+    let inference = infer::scan(&script);
+    let line = inference.line(&method.name);
+    if let Err(err) = line.parse::<Contract>() {
+        err.exit();
+    }
+
+    for flag in &inference.skipped {
+        eprintln!("The script parses the flag {flag}, which a contract can not declare");
+    }
+    println!("{line}");
+
+    if !append {
+        return;
+    }
+
+    if let Err(err) = self_contract(&method, &line) {
+        err.exit();
+    }
+    println!("Appended to {}", method.object.path.join(".self").display());
+}
+
+fn self_contract(method: &Method, line: &str) -> Result<(), Error> {
+    let declared = object_contracts(&method.object.path)?.is_some_and(|x| x.contains_key(&method.name))
+        || header::contract(&method.path, &method.name)?.is_some();
+
+    if declared {
+        return Err(Error::new(
+            ErrorCode::ContractExists,
+            "The method already has a contract, the inferred one is not appended",
+        )
+        .token(&method.dotted_name));
+    }
+
+    let path = method.object.path.join(".self");
+    let existing = match fs::read_to_string(&path) {
+        Ok(existing) => existing,
+        Err(err) if err.kind() == ErrorKind::NotFound => String::new(),
+        Err(err) => return Err(Error::io(&path, err)),
+    };
+
+    let separator = if existing.is_empty() || existing.ends_with('\n') { "" } else { "\n" };
+    let line = line.trim_start_matches("#>>>").trim_start();
+
+    OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&path)
+        .and_then(|mut x| x.write_all(format!("{separator}{line}\n").as_bytes()))
+        .map_err(|err| Error::io(&path, err))
+}
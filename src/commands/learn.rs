@@ -0,0 +1,56 @@
+use crate::audit;
+use crate::error::{Error, ErrorCode};
+use crate::learn::{self, Session};
+use crate::method::Method;
+use std::path::PathBuf;
+use std::time::Duration;
+
+pub fn start(prefix: Option<String>, duration: Option<Duration>) {
+    let session = Session {
+        prefix,
+        until: duration.map(|x| audit::now() + x.as_secs()),
+    };
+
+    if let Err(err) = learn::start(&session) {
+        Error::new(ErrorCode::Io, format!("Failed to start learning: {err}")).exit();
+    }
+
+    match &session.prefix {
+        Some(prefix) => println!("Observing the uncontracted methods under {prefix}"),
+        None => println!("Observing every uncontracted method"),
+    }
+}
+
+pub fn stop() {
+    match learn::stop() {
+        Ok(true) => println!("Stopped observing"),
+        Ok(false) => println!("Nothing is being observed"),
+        Err(err) => Error::new(ErrorCode::Io, format!("Failed to stop learning: {err}")).exit(),
+    }
+}
+
+/// Prints the suggested contract, and what to look at before adopting it on stderr.
+pub fn suggest(method: String) {
+    // The name becomes a path under the state dir, it is checked like any call.
+    let (name, method) = match Method::new(method, vec![], PathBuf::new()) {
+        Ok(method) => (method.name, method.dotted_name),
+        Err(err) => err.exit(),
+    };
+
+    let observations = match learn::observations(&method) {
+        Ok(observations) => observations,
+        Err(err) => Error::io(learn::observations_file(&method), err).exit(),
+    };
+    if observations.is_empty() {
+        Error::new(ErrorCode::NotObserved, format!("No calls of {method} were observed"))
+            .token(method)
+            .exit();
+    }
+
+    let suggestion = learn::suggest(&name, &observations);
+    for note in &suggestion.notes {
+        eprintln!("Note: {note}");
+    }
+    println!("{}", suggestion.line);
+    eprintln!("From {} observed calls", observations.len());
+}
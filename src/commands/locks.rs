@@ -0,0 +1,22 @@
+use crate::config::runtime_dir;
+use crate::error::Error;
+use crate::lock;
+
+pub fn execute() {
+    let dir = match runtime_dir() {
+        Ok(dir) => dir,
+        Err(err) => err.exit(),
+    };
+
+    match lock::held(&dir) {
+        Ok(locks) => {
+            for (path, holder) in locks {
+                let key = path.file_stem().unwrap_or_default().to_string_lossy();
+                let (pid, method) = holder.split_once(' ').unwrap_or((&holder, ""));
+
+                println!("{key} pid {pid} {method}");
+            }
+        }
+        Err(err) => Error::io(&dir, err).exit(),
+    }
+}
@@ -0,0 +1,181 @@
+use crate::audit::{Record, Validation, now};
+use crate::error::Error;
+use crate::supervisor::{TIMEOUT_EXIT_CODE, parse_duration};
+use clap::ValueEnum;
+use std::fs;
+use std::path::PathBuf;
+
+#[derive(ValueEnum, Clone, Copy, PartialEq)]
+pub enum Status {
+    /// The call was refused before the method was started
+    Rejected,
+    /// The call passed validation
+    Passed,
+    /// The method exited with 0
+    Ok,
+    /// The method exited with a non-zero code
+    Failed,
+    /// The method was terminated by the supervision timeout
+    Timeout,
+}
+
+impl Status {
+    fn matches(&self, record: &Record) -> bool {
+        match self {
+            Status::Rejected => record.validation == Validation::Rejected,
+            Status::Passed => record.validation == Validation::Passed,
+            Status::Ok => record.validation == Validation::Passed && record.exit_code == Some(0),
+            Status::Failed => {
+                record.validation == Validation::Passed
+                    && record.exit_code.is_some_and(|x| x != 0)
+            }
+            Status::Timeout => {
+                record.validation == Validation::Passed
+                    && record.exit_code == Some(TIMEOUT_EXIT_CODE)
+            }
+        }
+    }
+}
+
+pub struct Filter {
+    pub method: Option<String>,
+    pub since: Option<u64>,
+    pub until: Option<u64>,
+    pub status: Option<Status>,
+}
+
+impl Filter {
+    fn matches(&self, record: &Record) -> bool {
+        if let Some(method) = &self.method
+            && record.method != *method
+            && !record.method.starts_with(&format!("{method}."))
+        {
+            return false;
+        }
+
+        self.since.is_none_or(|x| record.timestamp >= x)
+            && self.until.is_none_or(|x| record.timestamp <= x)
+            && self.status.is_none_or(|x| x.matches(record))
+    }
+}
+
+pub fn execute(log: PathBuf, filter: Filter, json: bool) {
+    let content = match fs::read_to_string(&log) {
+        Ok(content) => content,
+        Err(err) => Error::io(&log, err).exit(),
+    };
+
+    for line in content.lines().filter(|x| !x.trim().is_empty()) {
+        let record = match serde_json::from_str::<Record>(line) {
+            Ok(record) => record,
+            Err(err) => {
+                eprintln!("Skipping a malformed audit record: {err}");
+                continue;
+            }
+        };
+
+        if !filter.matches(&record) {
+            continue;
+        }
+
+        if json {
+            println!("{line}");
+            continue;
+        }
+
+        let status = match (record.validation, record.exit_code) {
+            (Validation::Rejected, _) => "rejected".to_string(),
+            (Validation::Passed, Some(code)) => format!("exit {code}"),
+            (Validation::Passed, None) => "exec".to_string(),
+        };
+
+        println!(
+            "{} {:<10} {} {}",
+            format_timestamp(record.timestamp),
+            status,
+            record.method,
+            record.args.join(" ")
+        );
+    }
+}
+
+/// Parses a unix timestamp, or a duration such as `2h` that is counted back from now.
+pub fn parse_time(value: &str) -> Result<u64, String> {
+    if value.ends_with(|x: char| x.is_ascii_digit()) {
+        return value
+            .parse::<u64>()
+            .map_err(|_| format!("Invalid timestamp: {value}"));
+    }
+
+    parse_duration(value).map(|x| now().saturating_sub(x.as_secs()))
+}
+
+/// Formats a unix timestamp as `YYYY-MM-DD HH:MM:SS` in UTC.
+fn format_timestamp(timestamp: u64) -> String {
+    let days = (timestamp / 86400) as i64;
+    let seconds = timestamp % 86400;
+
+    // Days to civil date, see http://howardhinnant.github.io/date_algorithms.html
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    format!(
+        "{year:04}-{month:02}-{day:02} {:02}:{:02}:{:02}",
+        seconds / 3600,
+        seconds / 60 % 60,
+        seconds % 60
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn record(method: &str, timestamp: u64, exit_code: Option<i32>) -> Record {
+        Record {
+            timestamp,
+            method: method.into(),
+            path: PathBuf::default(),
+            args: vec![],
+            cwd: None,
+            stdin_piped: false,
+            stdout_piped: false,
+            validation: Validation::Passed,
+            exit_code,
+            duration_ms: None,
+        }
+    }
+
+    #[test]
+    fn filter() {
+        let filter = Filter {
+            method: Some("net.wifi".into()),
+            since: Some(100),
+            until: Some(200),
+            status: Some(Status::Failed),
+        };
+
+        assert!(filter.matches(&record("net.wifi.status", 150, Some(1))));
+        assert!(!filter.matches(&record("net.wifi.status", 150, Some(0))));
+        assert!(!filter.matches(&record("net.wifi2.status", 150, Some(1))));
+        assert!(!filter.matches(&record("net.wifi.status", 50, Some(1))));
+        assert!(!filter.matches(&record("net.wifi.status", 250, Some(1))));
+        assert!(!filter.matches(&record("net.wifi.status", 150, None)));
+    }
+
+    #[test]
+    fn timestamp() {
+        assert_eq!(format_timestamp(0), "1970-01-01 00:00:00");
+        assert_eq!(format_timestamp(951782400), "2000-02-29 00:00:00");
+        assert_eq!(format_timestamp(1790000000), "2026-09-21 14:13:20");
+        assert_eq!(parse_time("1790000000"), Ok(1790000000));
+        assert!(parse_time("1h").unwrap() <= now() - 3600);
+    }
+}
@@ -1,33 +1,107 @@
 use crate::contract::Contract;
-use std::collections::HashSet;
+use crate::error::{Error, ErrorCode};
+use crate::handlers::{object_contracts, parse_contracts, with_headers};
+use crate::index;
+use crate::strictness;
+use std::collections::{HashMap, HashSet};
 use std::fs::{DirEntry, ReadDir};
 use std::io::ErrorKind;
 use std::ops::Add;
-use std::path::PathBuf;
-use std::process::exit;
-use std::{fs, io};
-
-pub fn execute(namespace: PathBuf) {
-    match fs::read_dir(namespace) {
-        Ok(namespace) => {
-            let mut checked_links: HashSet<PathBuf> = HashSet::new();
-            if let Err(error) = recursive_output_methods(namespace, None, &mut checked_links) {
-                eprintln!("IO Error: {error}");
-                exit(1)
-            }
-        }
-        Err(error) => {
-            eprintln!("IO Error: {error}");
-            exit(1)
+use std::path::{Path, PathBuf};
+use std::fs;
+
+pub fn execute(namespace: PathBuf, cached: bool) {
+    let methods = match list(&namespace, cached) {
+        Ok(methods) => methods,
+        Err(err) => err.exit(),
+    };
+
+    let mut objects = HashMap::new();
+    for method in methods {
+        match deprecation(&namespace, &method, &mut objects) {
+            Some(note) if note.is_empty() => println!("{method} (deprecated)"),
+            Some(note) => println!("{method} (deprecated: {note})"),
+            None => println!("{method}"),
         }
     }
 }
 
+type Objects = HashMap<PathBuf, Option<HashMap<String, Contract>>>;
+
+/// The public methods of the namespace. With `cached`, they come from the index, which is
+/// then used by resolution for the rest of the process.
+pub fn list(namespace: &Path, cached: bool) -> Result<Vec<String>, Error> {
+    if !cached {
+        return collect(namespace);
+    }
+
+    let index = index::load(namespace)?;
+    let methods = index.methods().clone();
+    index::set(Some(index));
+    Ok(methods)
+}
+
+/// The `@deprecated` note of a method. Contracts are parsed once per object.
+fn deprecation(namespace: &Path, method: &str, objects: &mut Objects) -> Option<String> {
+    let read = |path: &PathBuf| object_contracts(path).ok().flatten();
+    contract(namespace, method, objects, read)?.deprecated().cloned()
+}
+
+/// The contract of a method by its dotted name, from the `.self` of the object or the
+/// `#>>>` header of the method file. `read` is called once per object.
+fn contract<'a>(
+    namespace: &Path,
+    method: &str,
+    objects: &'a mut Objects,
+    read: impl FnOnce(&PathBuf) -> Option<HashMap<String, Contract>>,
+) -> Option<&'a Contract> {
+    let (object, name) = method.rsplit_once('.')?;
+    let path = namespace.join(object.replace('.', "/"));
+    let name = name.to_string();
+
+    let contracts = objects.entry(path.clone()).or_insert_with(|| read(&path));
+    if contracts.as_ref().is_none_or(|x| !x.contains_key(&name))
+        && let Ok(with_header) = with_headers(&path, contracts.clone(), [&name])
+    {
+        *contracts = with_header;
+    }
+
+    contracts.as_ref()?.get(&name)
+}
+
+/// Dotted names of every method in the namespace, including the methods of interfaces.
+/// Private methods are left out.
+pub fn collect(namespace: &Path) -> Result<Vec<String>, Error> {
+    let mut methods = Vec::new();
+    let mut checked_links: HashSet<PathBuf> = HashSet::new();
+    recursive_output_methods(
+        fs::read_dir(namespace)?,
+        None,
+        &mut checked_links,
+        &mut methods,
+    )?;
+
+    // Read from disk, the index may be the stale one this listing rebuilds.
+    let read = |path: &PathBuf| {
+        let string = fs::read_to_string(path.join(".self")).ok()?;
+        parse_contracts(&string).ok()
+    };
+    let mut objects = Objects::new();
+    methods.retain(|method| {
+        let name = method.rsplit_once('.').map_or("", |x| x.1);
+        !name.starts_with('_')
+            && !contract(namespace, method, &mut objects, read).is_some_and(Contract::private)
+    });
+
+    Ok(methods)
+}
+
 fn recursive_output_methods(
     read_dir: ReadDir,
     object_name: Option<&str>,
     checked_links: &mut HashSet<PathBuf>,
-) -> io::Result<()> {
+    methods: &mut Vec<String>,
+) -> Result<(), Error> {
     for file in read_dir {
         let file = &file?;
 
@@ -54,6 +128,7 @@ fn recursive_output_methods(
                     fs::read_dir(dir)?,
                     Some(object_name.as_str()),
                     checked_links,
+                    methods,
                 )?;
 
                 checked_links.remove(&file.path());
@@ -75,7 +150,7 @@ fn recursive_output_methods(
             };
 
             if object_name.starts_with("__") && object_name.ends_with("__") {
-                interface_output_methods(file, object_name);
+                interface_output_methods(file, object_name, methods)?;
 
                 continue;
             }
@@ -84,6 +159,7 @@ fn recursive_output_methods(
                 fs::read_dir(file.path())?,
                 Some(object_name.as_str()),
                 checked_links,
+                methods,
             )?;
             continue;
         }
@@ -92,38 +168,59 @@ fn recursive_output_methods(
             continue
         }
 
-        if let Some(object) = object_name {
-            if let Some(method) = file.file_name().to_str() {
-                println!("{object}.{method}")
-            }
+        if let Some(object) = object_name
+            && let Some(method) = file.file_name().to_str()
+        {
+            methods.push(format!("{object}.{method}"))
         }
     }
 
     Ok(())
 }
 
-fn interface_output_methods(dir: &DirEntry, interface_name: String) {
+fn interface_output_methods(
+    dir: &DirEntry,
+    interface_name: String,
+    methods: &mut Vec<String>,
+) -> Result<(), Error> {
     match fs::read_to_string(dir.path().join(".self")) {
         Ok(contracts) => {
-            for line in contracts.lines() {
-                match line.parse::<Contract>() {
-                    Ok(contract) => {
-                        println!("{interface_name}.{}", contract.name())
-                    }
-                    Err(_err) => {
-                        eprintln!("Error while parsing contract: {line}");
-                        exit(1)
-                    }
-                }
+            for line in contracts.lines().filter(|x| !strictness::is_directive(x)) {
+                let contract = line.parse::<Contract>()?;
+                methods.push(format!("{interface_name}.{}", contract.name()))
             }
+
+            Ok(())
         }
-        Err(err) if err.kind() == ErrorKind::NotFound => {
-            eprintln!("Interfaces must contain a contracts file named \".self\"");
-            exit(1)
-        }
-        Err(err) => {
-            eprintln!("IO Error: {err}");
-            exit(1)
-        }
+        Err(err) if err.kind() == ErrorKind::NotFound => Err(Error::new(
+            ErrorCode::InterfaceContractMissing,
+            "Interfaces must contain a contracts file named \".self\"",
+        )
+        .token(interface_name)),
+        Err(err) => Err(Error::io(dir.path(), err)),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::temp_dir::TempDir;
+
+    #[test]
+    fn headers() {
+        let namespace = TempDir::new("methods");
+        fs::create_dir_all(namespace.join("net/wifi")).unwrap();
+        fs::write(namespace.join("net/wifi/.self"), "status: stdout?\n").unwrap();
+        fs::write(namespace.join("net/wifi/status"), "").unwrap();
+        fs::write(namespace.join("net/wifi/reset"), "#!/bin/sh\n#>>> private\n").unwrap();
+        fs::write(namespace.join("net/wifi/scan"), "#!/bin/sh\n#>>> stdout! @deprecated \"use list\"\n").unwrap();
+
+        let mut methods = collect(&namespace).unwrap();
+        methods.sort();
+        assert_eq!(methods, ["net.wifi.scan", "net.wifi.status"]);
+
+        let mut objects = Objects::new();
+        assert_eq!(deprecation(&namespace, "net.wifi.scan", &mut objects), Some("use list".into()));
+        assert_eq!(deprecation(&namespace, "net.wifi.status", &mut objects), None);
     }
 }
@@ -1,2 +1,12 @@
+#[allow(clippy::module_inception)]
 pub mod commands;
-pub mod methods;
\ No newline at end of file
+pub mod cache;
+pub mod check;
+pub mod fuzz;
+pub mod infer;
+pub mod learn;
+pub mod locks;
+pub mod log;
+pub mod methods;
+pub mod state;
+pub mod test;
\ No newline at end of file
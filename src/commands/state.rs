@@ -0,0 +1,54 @@
+use crate::config::state_dir;
+use crate::error::{Error, ErrorCode};
+use crate::state;
+use std::fs;
+
+fn validate(object: &str) {
+    if !state::is_valid(object) {
+        Error::new(ErrorCode::InvalidName, "The object does not match the pattern")
+            .token(object)
+            .exit();
+    }
+}
+
+/// Prints the state directories of an object and of its instances with their files.
+pub fn show(object: String) {
+    validate(&object);
+    let root = state_dir();
+
+    let dirs = match state::find(&root, &object) {
+        Ok(dirs) => dirs,
+        Err(err) => Error::io(&root, err).exit(),
+    };
+
+    for dir in dirs {
+        println!("{}", dir.display());
+        match state::files(&dir) {
+            Ok(files) => files.iter().for_each(|x| println!("  {}", x.display())),
+            Err(err) => Error::io(&dir, err).exit(),
+        }
+    }
+}
+
+/// Removes the state of an object and of its instances, or of one `object#instance`.
+/// Their `.init` runs again on the next call.
+pub fn reset(object: String) {
+    validate(&object);
+    let root = state_dir();
+
+    let dirs = match state::find(&root, &object) {
+        Ok(dirs) => dirs,
+        Err(err) => Error::io(&root, err).exit(),
+    };
+    if dirs.is_empty() {
+        println!("{object} has no state");
+        return;
+    }
+
+    for dir in dirs {
+        if let Err(err) = fs::remove_dir_all(&dir) {
+            Error::io(&dir, err).exit();
+        }
+    }
+    println!("Reset the state of {object}");
+}
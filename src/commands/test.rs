@@ -0,0 +1,251 @@
+use crate::commands::methods;
+use crate::config::runtime_dir;
+use crate::error::{Error, ErrorCode};
+use crate::handlers::{Handle, ResolveHandler};
+use crate::method::Method;
+use crate::mock::{self, Mocks};
+use crate::suite::{self, Case, Outcome};
+use crate::supervisor;
+use clap::ValueEnum;
+use serde_json::Value;
+use std::collections::BTreeSet;
+use std::fs::{self, DirBuilder, File};
+use std::os::unix::fs::DirBuilderExt;
+use std::path::{self, Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::{env, io};
+
+/// Numbers the working directories of the calls.
+static RUNS: AtomicUsize = AtomicUsize::new(0);
+
+#[derive(ValueEnum, Clone, Copy, PartialEq, Debug, Default)]
+pub enum ReportFormat {
+    #[default]
+    Tap,
+    Junit,
+}
+
+/// Runs the `.tests` cases of every object with a method under `prefix`, with the methods
+/// in `mocks` replaced in every call the cases make.
+pub fn execute(
+    namespace: PathBuf,
+    prefix: Option<String>,
+    format: ReportFormat,
+    mocks: Mocks,
+    cached: bool,
+) {
+    let namespace = path::absolute(&namespace).unwrap_or(namespace);
+    let methods = match methods::list(&namespace, cached) {
+        Ok(methods) => methods,
+        Err(err) => err.exit(),
+    };
+
+    let matches = |x: &str| {
+        prefix
+            .as_ref()
+            .is_none_or(|prefix| x == prefix || x.starts_with(&format!("{prefix}.")))
+    };
+
+    let objects = methods
+        .iter()
+        .filter_map(|x| x.rsplit_once('.').map(|x| x.0.to_string()))
+        .collect::<BTreeSet<_>>();
+
+    let mut outcomes = Vec::new();
+    for object in objects {
+        let cases = match suite::load(&namespace.join(object.replace('.', "/"))) {
+            Ok(cases) => cases,
+            Err(err) => {
+                outcomes.push(Outcome {
+                    method: object.clone(),
+                    title: ".tests".into(),
+                    failure: Some(err.to_string()),
+                });
+                continue;
+            }
+        };
+
+        for case in cases {
+            let method = format!("{object}.{}", case.method);
+            if !matches(&method) {
+                continue;
+            }
+
+            outcomes.push(Outcome {
+                failure: run(&namespace, &method, &case, &mocks, cached).err(),
+                title: case.title(),
+                method,
+            });
+        }
+    }
+
+    match format {
+        ReportFormat::Tap => print!("{}", suite::tap(&outcomes)),
+        ReportFormat::Junit => print!("{}", suite::junit(&outcomes)),
+    }
+
+    let failed = outcomes.iter().filter(|x| x.failure.is_some()).count();
+    if failed > 0 {
+        Error::new(
+            ErrorCode::TestFailed,
+            format!("{failed} of {} cases failed", outcomes.len()),
+        )
+        .exit();
+    }
+}
+
+/// Runs one case and compares the call with its expectations.
+fn run(namespace: &Path, method: &str, case: &Case, mocks: &Mocks, cached: bool) -> Result<(), String> {
+    if case.error.is_none() && case.exit_code != 0 {
+        declared(namespace, method, case)?;
+    }
+    if case.calls.is_some() && mocks.is_empty() {
+        return Err("the case expects mocked calls, but no method is mocked".into());
+    }
+
+    let stdin = match (&case.stdin, &case.stdin_file) {
+        (Some(text), _) => Fixture::Text(text),
+        (None, Some(path)) => Fixture::File(case.dir.join(path)),
+        (None, None) => Fixture::Null,
+    };
+
+    let run = call(namespace, method, &case.args, stdin, &[], mocks, cached).map_err(|err| err.to_string())?;
+    let stderr = &run.stderr;
+
+    if let Some(code) = &case.error {
+        let refused = stderr
+            .lines()
+            .filter_map(|x| serde_json::from_str::<Value>(x).ok())
+            .any(|x| x["code"] == *code);
+
+        return match refused {
+            true => Ok(()),
+            false => Err(format!(
+                "expected the error {code}, exited with {}: {}",
+                run.exit_code,
+                stderr.trim()
+            )),
+        };
+    }
+
+    if run.exit_code != case.exit_code {
+        return Err(format!(
+            "exited with {}, expected {}: {}",
+            run.exit_code,
+            case.exit_code,
+            stderr.trim()
+        ));
+    }
+
+    case.check_stdout(&run.stdout)?;
+    case.check_calls(&run.calls)
+}
+
+/// An expected exit code that the contract does not declare can never pass `irnix e`.
+fn declared(namespace: &Path, method: &str, case: &Case) -> Result<(), String> {
+    let mut resolved =
+        Method::new(method.to_string(), vec![], namespace.to_path_buf()).map_err(|x| x.to_string())?;
+    ResolveHandler::handle(&mut resolved).map_err(|x| x.to_string())?;
+
+    match resolved.contract() {
+        Some(contract) if !contract.error_codes().contains(&(case.exit_code as u32)) => Err(format!(
+            "the expected exit code {} is not declared by the contract",
+            case.exit_code
+        )),
+        _ => Ok(()),
+    }
+}
+
+/// Stdin of a call made by `irnix test` or `irnix fuzz`.
+pub(crate) enum Fixture<'a> {
+    Text(&'a str),
+    File(PathBuf),
+    Null,
+}
+
+/// What a call made by [`call`] did.
+pub(crate) struct Run {
+    pub exit_code: i32,
+    pub stdout: Vec<u8>,
+    pub stderr: String,
+    /// Calls of mocked methods, in order.
+    pub calls: Vec<mock::Call>,
+}
+
+/// Calls the method through `irnix e` in a temporary working directory, with stdout to
+/// a file so that any stdout contract accepts it. `options` go to `irnix e`.
+pub(crate) fn call(
+    namespace: &Path,
+    method: &str,
+    args: &[String],
+    stdin: Fixture,
+    options: &[String],
+    mocks: &Mocks,
+    cached: bool,
+) -> Result<Run, Error> {
+    // The fixtures and the mocks that `irnix e` runs are written here, so it is created in
+    // the private runtime directory and never reused.
+    let dir = runtime_dir()?.join(format!(
+        "test-{}-{}",
+        std::process::id(),
+        RUNS.fetch_add(1, Ordering::Relaxed)
+    ));
+    // Left over by an earlier process with the same pid.
+    let _ = fs::remove_dir_all(&dir);
+    DirBuilder::new()
+        .mode(0o700)
+        .create(&dir)
+        .map_err(|err| Error::io(&dir, err))?;
+
+    let mut command = Command::new(env::current_exe()?);
+    command
+        .args((!cached).then_some("--no-cache"))
+        .args(["--error-format", "json", "execute", "--namespace"])
+        .arg(namespace)
+        .arg("--cwd")
+        .arg(dir.join("work"))
+        .args(options);
+
+    if !mocks.is_empty() {
+        command.arg("--mocks").arg(dir.join("mocks"));
+    }
+    command.arg("--").arg(method).args(args);
+
+    let result = match mocks.is_empty() {
+        true => call_in(command, stdin, &dir),
+        false => mocks
+            .save(&dir.join("mocks"))
+            .and_then(|_| call_in(command, stdin, &dir)),
+    }
+    .map_err(Error::from);
+    let _ = fs::remove_dir_all(&dir);
+    result
+}
+
+fn call_in(mut command: Command, stdin: Fixture, dir: &Path) -> io::Result<Run> {
+    fs::create_dir(dir.join("work"))?;
+
+    let stdin = match stdin {
+        Fixture::Text(text) => {
+            fs::write(dir.join("stdin"), text)?;
+            Stdio::from(File::open(dir.join("stdin"))?)
+        }
+        Fixture::File(path) => Stdio::from(File::open(path)?),
+        Fixture::Null => Stdio::null(),
+    };
+
+    let stdout = dir.join("stdout");
+    let output = command
+        .stdin(stdin)
+        .stdout(File::create(&stdout)?)
+        .stderr(Stdio::piped())
+        .output()?;
+
+    Ok(Run {
+        exit_code: supervisor::exit_code(output.status),
+        stdout: fs::read(&stdout)?,
+        stderr: String::from_utf8_lossy(&output.stderr).to_string(),
+        calls: mock::calls(&mock::log(&dir.join("mocks")))?,
+    })
+}
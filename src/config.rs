@@ -1,13 +1,19 @@
+use crate::error::{Error, ErrorFormat};
+use crate::strictness::Strictness;
+use clap::ValueEnum;
 use std::env;
-use std::path::PathBuf;
+use std::fs::{self, DirBuilder};
+use std::io;
+use std::os::unix::fs::{DirBuilderExt, MetadataExt};
+use std::path::{self, Path, PathBuf};
 
 pub struct Config {
     namespace: PathBuf,
 }
 
-impl Into<PathBuf> for Config {
-    fn into(self) -> PathBuf {
-        self.namespace
+impl From<Config> for PathBuf {
+    fn from(config: Config) -> Self {
+        config.namespace
     }
 }
 
@@ -27,3 +33,98 @@ pub fn unwrap_namespace(namespace: Option<PathBuf>) -> PathBuf {
             .unwrap_or(Config::default().into()),
     )
 }
+
+/// Path of the JSON-lines audit log. The log is disabled unless `IRNIX_AUDIT_LOG` is set.
+pub fn audit_log() -> Option<PathBuf> {
+    env::var("IRNIX_AUDIT_LOG")
+        .ok()
+        .filter(|x| !x.is_empty())
+        .map(PathBuf::from)
+}
+
+/// `$XDG_CACHE_HOME/irnix`, or `~/.cache/irnix` if it is not set.
+pub fn cache_dir() -> PathBuf {
+    env::var("XDG_CACHE_HOME")
+        .ok()
+        .filter(|x| !x.is_empty())
+        .map(PathBuf::from)
+        .unwrap_or_else(|| env::home_dir().unwrap().join(".cache"))
+        .join("irnix")
+}
+
+/// `$XDG_STATE_HOME/irnix`, or `~/.local/state/irnix` if it is not set.
+pub fn state_dir() -> PathBuf {
+    env::var("XDG_STATE_HOME")
+        .ok()
+        .filter(|x| !x.is_empty())
+        .map(PathBuf::from)
+        .unwrap_or_else(|| env::home_dir().unwrap().join(".local/state"))
+        .join("irnix")
+}
+
+/// `$XDG_RUNTIME_DIR/irnix`, or a directory per user in the temporary directory if it is not set.
+///
+/// The directory is created with mode 0700. Since the temporary directory is shared and the
+/// path predictable, it is refused unless it is owned by the user with exactly that mode.
+pub fn runtime_dir() -> Result<PathBuf, Error> {
+    let uid = unsafe { libc::getuid() };
+    let dir = env::var("XDG_RUNTIME_DIR")
+        .ok()
+        .filter(|x| !x.is_empty())
+        .map(|x| PathBuf::from(x).join("irnix"))
+        .unwrap_or_else(|| env::temp_dir().join(format!("irnix-{uid}")));
+
+    let metadata = DirBuilder::new()
+        .recursive(true)
+        .mode(0o700)
+        .create(&dir)
+        .and_then(|_| fs::symlink_metadata(&dir))
+        .map_err(|err| Error::io(&dir, err))?;
+
+    if !metadata.is_dir() || metadata.uid() != uid || metadata.mode() & 0o777 != 0o700 {
+        return Err(Error::io(
+            &dir,
+            io::Error::new(
+                io::ErrorKind::PermissionDenied,
+                "the runtime directory must be owned by the user with mode 0700",
+            ),
+        ));
+    }
+
+    Ok(dir)
+}
+
+/// The program used to reach remote hosts, `IRNIX_SSH` or `ssh`.
+pub fn ssh_program() -> PathBuf {
+    env::var("IRNIX_SSH")
+        .ok()
+        .filter(|x| !x.is_empty())
+        .map(PathBuf::from)
+        .unwrap_or("ssh".into())
+}
+
+/// The namespace on remote hosts. Defaults to `IRNIX_REMOTE_NAMESPACE`, then to the local one.
+pub fn unwrap_remote_namespace(namespace: Option<PathBuf>, local: &Path) -> PathBuf {
+    namespace
+        .or(env::var("IRNIX_REMOTE_NAMESPACE").ok().map(PathBuf::from))
+        .unwrap_or(path::absolute(local).unwrap_or(local.to_path_buf()))
+}
+
+pub fn unwrap_error_format(format: Option<ErrorFormat>) -> ErrorFormat {
+    format.unwrap_or(
+        env::var("IRNIX_ERROR_FORMAT")
+            .ok()
+            .and_then(|x| ErrorFormat::from_str(&x, true).ok())
+            .unwrap_or_default(),
+    )
+}
+
+/// The strictness policy of objects without an `@strict` directive, `IRNIX_STRICT` or off.
+pub fn unwrap_strictness(strictness: Option<Strictness>) -> Strictness {
+    strictness.unwrap_or(
+        env::var("IRNIX_STRICT")
+            .ok()
+            .and_then(|x| Strictness::from_str(&x, true).ok())
+            .unwrap_or_default(),
+    )
+}
@@ -1,17 +1,35 @@
 use crate::contract_tokens::ContractTokens;
-use logos::Logos;
+use crate::error::{Error, ErrorCode};
+use crate::lock::{LockScope, LockWait, parse_wait};
+use crate::stream::{Format, Stream};
+use crate::supervisor::{Supervision, parse_duration, parse_size};
+use logos::{Lexer, Logos};
 use std::collections::HashSet;
-use std::process::exit;
+use std::fmt::{Display, Formatter};
 use std::str::FromStr;
+use std::time::Duration;
 
-#[derive(PartialEq, Debug)]
+#[derive(PartialEq, Debug, Clone)]
 pub struct Contract {
     stdin: Stdin,
+    stdin_stream: Option<Stream>,
+    stdin_format: Option<Format>,
     args: Vec<Arg>,
     flags: HashSet<Flag>,
     stdout: Stdout,
     error_codes: Vec<u32>,
     name: String,
+    supervision: Option<Supervision>,
+    groups: Vec<FlagGroup>,
+    pure: bool,
+    ttl: Option<Duration>,
+    lock: Option<LockScope>,
+    lock_wait: Option<LockWait>,
+    version: Option<u32>,
+    min_version: Option<u32>,
+    deprecated: Option<String>,
+    private: bool,
+    state: bool,
 }
 
 impl Contract {
@@ -26,16 +44,35 @@ impl Contract {
     ) -> Contract {
         Contract {
             stdin: stdin.into(),
+            stdin_stream: None,
+            stdin_format: None,
             args,
             flags,
             stdout: stdout.into(),
             error_codes,
             name,
+            supervision: None,
+            groups: vec![],
+            pure: false,
+            ttl: None,
+            lock: None,
+            lock_wait: None,
+            version: None,
+            min_version: None,
+            deprecated: None,
+            private: false,
+            state: false,
         }
     }
 
-    pub fn required_args(&self) -> Vec<Arg> {
-        self.args.iter().cloned().filter(|x| x.required).collect()
+    /// The fewest positional values the contract accepts.
+    pub fn min_args(&self) -> usize {
+        self.args.iter().map(|x| x.min).sum()
+    }
+
+    /// The most positional values the contract accepts, `None` if it has a variadic argument.
+    pub fn max_args(&self) -> Option<usize> {
+        self.args.iter().map(|x| x.max).sum()
     }
 
     pub fn args(&self) -> Vec<Arg> {
@@ -54,90 +91,464 @@ impl Contract {
         &self.stdin
     }
 
+    /// The stream stdin must come from, declared as `stdin:pipe!`, `stdin:file?` or `stdin:tty!`.
+    pub fn stdin_stream(&self) -> Option<Stream> {
+        self.stdin_stream
+    }
+
+    /// The format stdin is checked against before the method starts, declared with `@stdin_format`.
+    pub fn stdin_format(&self) -> Option<Format> {
+        self.stdin_format
+    }
+
     pub fn stdout(&self) -> &Stdout {
         &self.stdout
     }
+
+    /// Limits declared with `@supervised`, `@timeout`, `@cpu`, `@memory` or `@files`.
+    pub fn supervision(&self) -> Option<&Supervision> {
+        self.supervision.as_ref()
+    }
+
+    pub fn error_codes(&self) -> &Vec<u32> {
+        &self.error_codes
+    }
+
+    /// Whether results may be cached, declared with `@pure` or `@ttl`.
+    pub fn pure(&self) -> bool {
+        self.pure
+    }
+
+    /// How long a cached result stays valid, unlimited if `None`.
+    pub fn ttl(&self) -> Option<Duration> {
+        self.ttl
+    }
+
+    /// Declared with `@lock` or `@lock(object)`.
+    pub fn lock(&self) -> Option<LockScope> {
+        self.lock
+    }
+
+    /// Declared with `@lock_wait fail`, `@lock_wait wait` or `@lock_wait 30s`.
+    pub fn lock_wait(&self) -> Option<LockWait> {
+        self.lock_wait
+    }
+
+    pub fn groups(&self) -> &Vec<FlagGroup> {
+        &self.groups
+    }
+
+    /// Declared with `@version N`. A contract without one is version 1.
+    pub fn version(&self) -> u32 {
+        self.version.unwrap_or(1)
+    }
+
+    /// The lowest version of the target contract an interface accepts, declared with `@min_version N`.
+    pub fn min_version(&self) -> Option<u32> {
+        self.min_version
+    }
+
+    /// Declared with `@deprecated` or `@deprecated "use net.wifi.join"`.
+    pub fn deprecated(&self) -> Option<&String> {
+        self.deprecated.as_ref()
+    }
+
+    /// Declared with the `private` keyword. Private methods are hidden from listings and
+    /// only callable from methods of the same object.
+    pub fn private(&self) -> bool {
+        self.private
+    }
+
+    /// Declared with `@state`. The state directory of the object is created for the call,
+    /// which objects with an `.init` or an instance get without it.
+    pub fn state(&self) -> bool {
+        self.state
+    }
+
+    /// Whether both contracts accept and produce the same calls. Versions and deprecation
+    /// notes are not part of the signature.
+    pub fn same_signature(&self, other: &Contract) -> bool {
+        let signature = |x: &Contract| Contract {
+            version: None,
+            min_version: None,
+            deprecated: None,
+            ..x.clone()
+        };
+
+        signature(self) == signature(other)
+    }
 }
 
 impl FromStr for Contract {
-    type Err = ();
+    type Err = Error;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
         let mut lex = ContractTokens::lexer(s);
 
         let mut stdin = Stdin::None;
+        let mut stdin_stream: Option<Stream> = None;
+        let mut stdin_format: Option<Format> = None;
         let mut stdout = Stdout::None;
         let mut args: Vec<Arg> = Vec::new();
         let mut flags: HashSet<Flag> = HashSet::new();
         let mut error_codes: Vec<u32> = Vec::new();
         let mut name: Option<String> = None;
+        let mut supervision: Option<Supervision> = None;
+        let mut groups: Vec<FlagGroup> = Vec::new();
+        let mut pure = false;
+        let mut ttl: Option<Duration> = None;
+        let mut lock: Option<LockScope> = None;
+        let mut lock_wait: Option<LockWait> = None;
+        let mut version: Option<u32> = None;
+        let mut min_version: Option<u32> = None;
+        let mut deprecated: Option<String> = None;
+        let mut private = false;
+        let mut state = false;
 
         while let Some(token) = lex.next() {
-            match token? {
-                ContractTokens::Stdin => stdin = Stdin::from(lex.slice()),
+            let Ok(token) = token else {
+                return Err(parse_error(s, lex.slice()));
+            };
+
+            match token {
+                ContractTokens::Stdin => {
+                    stdin = Stdin::from(lex.slice());
+                    stdin_stream = lex.slice()[..lex.slice().len() - 1]
+                        .split_once(":")
+                        .and_then(|x| Stream::from_mode(x.1));
+                }
                 ContractTokens::Stdout => stdout = Stdout::from(lex.slice()),
-                ContractTokens::Arg => args.push(Arg::from(lex.slice())),
-                ContractTokens::Flag => {
-                    flags.insert(Flag::from(lex.slice()));
+                ContractTokens::Arg | ContractTokens::VariadicArg => args.push(lex.slice().parse()?),
+                ContractTokens::RangeArg => {
+                    let arg = lex.slice().parse::<Arg>()?;
+                    if arg.max.is_some_and(|x| x < arg.min || x == 0) {
+                        return Err(parse_error(s, lex.slice()));
+                    }
+                    args.push(arg);
                 }
-                ContractTokens::Number => {
-                    error_codes.push(lex.slice().parse::<u32>().map_err(|_| ())?)
+                ContractTokens::Flag | ContractTokens::RepeatableFlag => {
+                    flags.insert(lex.slice().parse()?);
                 }
+                ContractTokens::Group => groups.push(lex.slice().parse()?),
+                ContractTokens::Number => error_codes.push(
+                    lex.slice()
+                        .parse::<u32>()
+                        .map_err(|_| parse_error(s, lex.slice()))?,
+                ),
                 ContractTokens::Name => name = Some(lex.slice().replace(":", "")),
+                ContractTokens::Annotation => {
+                    let annotation = lex.slice().to_string();
+                    let invalid = |_| parse_error(s, &annotation);
+
+                    match &annotation[1..] {
+                        "supervised" => {
+                            supervision.get_or_insert_default();
+                        }
+                        "timeout" => {
+                            let value = parse_duration(&annotation_value(s, &mut lex)?).map_err(invalid)?;
+                            supervision.get_or_insert_default().timeout = Some(value);
+                        }
+                        "cpu" => {
+                            let value = parse_duration(&annotation_value(s, &mut lex)?).map_err(invalid)?;
+                            supervision.get_or_insert_default().cpu = Some(value);
+                        }
+                        "memory" => {
+                            let value = parse_size(&annotation_value(s, &mut lex)?).map_err(invalid)?;
+                            supervision.get_or_insert_default().memory = Some(value);
+                        }
+                        // A number of descriptors, not a size.
+                        "files" => {
+                            let value = annotation_value(s, &mut lex)?
+                                .parse::<u64>()
+                                .map_err(|_| parse_error(s, &annotation))?;
+                            supervision.get_or_insert_default().files = Some(value);
+                        }
+                        // Results are cached in supervised mode only.
+                        "pure" => {
+                            pure = true;
+                            supervision.get_or_insert_default();
+                        }
+                        "ttl" => {
+                            let value = parse_duration(&annotation_value(s, &mut lex)?).map_err(invalid)?;
+                            (pure, ttl) = (true, Some(value));
+                            supervision.get_or_insert_default();
+                        }
+                        "lock" => lock = Some(lock_scope(s, &mut lex)?),
+                        "lock_wait" => {
+                            let value = match lex.next() {
+                                Some(Ok(
+                                    ContractTokens::Word
+                                    | ContractTokens::Number
+                                    | ContractTokens::Quantity,
+                                )) => lex.slice().to_string(),
+                                _ => return Err(parse_error(s, lex.slice())),
+                            };
+                            lock_wait = Some(parse_wait(&value).map_err(|_| parse_error(s, &value))?);
+                        }
+                        // Stdin has to be buffered to be checked, so the method is supervised.
+                        "stdin_format" => {
+                            let value = match lex.next() {
+                                Some(Ok(ContractTokens::Word)) => lex.slice(),
+                                _ => return Err(parse_error(s, lex.slice())),
+                            };
+                            stdin_format = Some(value.parse().map_err(|_| parse_error(s, value))?);
+                            supervision.get_or_insert_default();
+                        }
+                        "state" => state = true,
+                        "version" => version = Some(annotation_number(s, &mut lex)?),
+                        "min_version" => min_version = Some(annotation_number(s, &mut lex)?),
+                        "deprecated" => {
+                            let mut ahead = lex.clone();
+                            deprecated = match ahead.next() {
+                                Some(Ok(ContractTokens::Str)) => {
+                                    lex.next();
+                                    Some(unquote(lex.slice()))
+                                }
+                                _ => Some(String::new()),
+                            };
+                        }
+                        _ => return Err(parse_error(s, &annotation)),
+                    }
+                }
+                ContractTokens::Word if lex.slice() == "private" => private = true,
+                ContractTokens::Quantity | ContractTokens::Word | ContractTokens::Str => {
+                    return Err(parse_error(s, lex.slice()));
+                }
                 _ => continue,
             }
         }
 
-        if name.is_none() {
-            eprintln!(
-                "Contract must have a name that matches a method name. No name was provided."
-            );
-            exit(2);
+        let Some(name) = name else {
+            return Err(Error::new(
+                ErrorCode::ContractParse,
+                "Contract must have a name that matches a method name. No name was provided.",
+            )
+            .token(s));
+        };
+
+        for group in &groups {
+            let undeclared = group
+                .flags()
+                .iter()
+                .any(|x| !flags.iter().any(|flag| flag == x));
+
+            if group.flags().len() < 2 || undeclared {
+                return Err(parse_error(s, &group.to_string()));
+            }
         }
 
         Ok(Contract {
             stdin,
+            stdin_stream,
+            stdin_format,
             args,
             flags,
             stdout,
             error_codes,
-            name: name.unwrap(),
+            name,
+            supervision,
+            groups,
+            pure,
+            ttl,
+            lock,
+            lock_wait,
+            version,
+            min_version,
+            deprecated,
+            private,
+            state,
         })
     }
 }
 
+fn parse_error(contract: &str, token: &str) -> Error {
+    Error::new(
+        ErrorCode::ContractParse,
+        format!("Error while parsing contract: {contract}"),
+    )
+    .token(token)
+}
+
+fn annotation_value(contract: &str, lex: &mut Lexer<ContractTokens>) -> Result<String, Error> {
+    match lex.next() {
+        Some(Ok(ContractTokens::Number | ContractTokens::Quantity)) => Ok(lex.slice().to_string()),
+        _ => Err(parse_error(contract, lex.slice())),
+    }
+}
+
+fn annotation_number(contract: &str, lex: &mut Lexer<ContractTokens>) -> Result<u32, Error> {
+    match lex.next() {
+        Some(Ok(ContractTokens::Number)) => lex
+            .slice()
+            .parse::<u32>()
+            .map_err(|_| parse_error(contract, lex.slice())),
+        _ => Err(parse_error(contract, lex.slice())),
+    }
+}
+
+/// The text of a `"..."` token, with `\"` and `\\` unescaped.
+fn unquote(token: &str) -> String {
+    let mut text = String::new();
+    let mut chars = token[1..token.len() - 1].chars();
+
+    while let Some(char) = chars.next() {
+        match char {
+            '\\' => text.extend(chars.next()),
+            _ => text.push(char),
+        }
+    }
+
+    text
+}
+
+/// Reads the optional `(method)` or `(object)` after `@lock`.
+fn lock_scope(contract: &str, lex: &mut Lexer<ContractTokens>) -> Result<LockScope, Error> {
+    let mut ahead = lex.clone();
+    if ahead.next() != Some(Ok(ContractTokens::LP)) {
+        return Ok(LockScope::Method);
+    }
+
+    let scope = match (ahead.next(), ahead.slice()) {
+        (Some(Ok(ContractTokens::Word)), "method") => LockScope::Method,
+        (Some(Ok(ContractTokens::Word)), "object") => LockScope::Object,
+        // `@lock (arg!)` is a lock followed by a parenthesized argument list.
+        _ => return Ok(LockScope::Method),
+    };
+
+    match ahead.next() {
+        Some(Ok(ContractTokens::RP)) => {
+            *lex = ahead;
+            Ok(scope)
+        }
+        _ => Err(parse_error(contract, ahead.slice())),
+    }
+}
+
+/// A positional argument. `name!` and `name?` take one value, `name...!` one or more,
+/// `name...?` any number and `name{2,5}` a range of values.
 #[derive(PartialEq, Debug, Clone)]
 pub struct Arg {
-    required: bool,
+    name: String,
+    min: usize,
+    max: Option<usize>,
+    kind: ArgKind,
 }
 
-impl From<String> for Arg {
-    fn from(value: String) -> Self {
-        if value.ends_with("!") {
-            return Arg { required: true };
+/// The type of an argument, written as `count<int>!` or `ratio<num>?`.
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum ArgKind {
+    Text,
+    Int,
+    Num,
+}
+
+impl ArgKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ArgKind::Text => "text",
+            ArgKind::Int => "int",
+            ArgKind::Num => "num",
         }
+    }
 
-        if value.ends_with("?") {
-            return Arg { required: false };
+    pub fn is_numeric(&self) -> bool {
+        !matches!(self, ArgKind::Text)
+    }
+
+    pub fn accepts(&self, value: &str) -> bool {
+        match self {
+            ArgKind::Text => true,
+            ArgKind::Int => value.parse::<i64>().is_ok(),
+            ArgKind::Num => value.parse::<f64>().is_ok_and(|x| x.is_finite()),
         }
+    }
+}
+
+impl Arg {
+    pub fn name(&self) -> &String {
+        &self.name
+    }
+
+    #[allow(dead_code)]
+    pub fn is_required(&self) -> bool {
+        self.min > 0
+    }
+
+    pub fn min(&self) -> usize {
+        self.min
+    }
+
+    /// `None` if the argument takes any number of values.
+    pub fn max(&self) -> Option<usize> {
+        self.max
+    }
+
+    pub fn kind(&self) -> ArgKind {
+        self.kind
+    }
+}
 
-        eprintln!("The argument must be explicitly defined as required or optional using ! or ?");
-        exit(2)
+#[cfg(test)]
+impl From<String> for Arg {
+    fn from(value: String) -> Self {
+        Arg::from(value.as_str())
     }
 }
 
+/// For declaring an argument in tests, panics if it is not valid.
+#[cfg(test)]
 impl From<&str> for Arg {
     fn from(value: &str) -> Self {
-        if value.ends_with("!") {
-            return Arg { required: true };
-        }
+        value.parse().unwrap()
+    }
+}
 
-        if value.ends_with("?") {
-            return Arg { required: false };
-        }
+impl FromStr for Arg {
+    type Err = Error;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let token = value;
+        let (value, min, max) = if let Some(value) = value.strip_suffix("...!") {
+            (value, 1, None)
+        } else if let Some(value) = value.strip_suffix("...?") {
+            (value, 0, None)
+        } else if let Some(value) = value.strip_suffix("!") {
+            (value, 1, Some(1))
+        } else if let Some(value) = value.strip_suffix("?") {
+            (value, 0, Some(1))
+        } else if let Some((value, range)) = value
+            .strip_suffix("}")
+            .and_then(|x| x.split_once("{"))
+        {
+            let bound = |x: &str| x.parse::<usize>().ok();
+            match range.split_once(",") {
+                Some((min, max)) => (value, bound(min).unwrap_or(0), bound(max)),
+                None => (value, bound(range).unwrap_or(0), bound(range)),
+            }
+        } else {
+            return Err(Error::new(
+                ErrorCode::ContractParse,
+                "The argument must be explicitly defined as required or optional using ! or ?",
+            )
+            .token(token));
+        };
+
+        let (name, kind) = match value.split_once("<") {
+            Some((name, "int>")) => (name, ArgKind::Int),
+            Some((name, "num>")) => (name, ArgKind::Num),
+            Some((_, kind)) => {
+                return Err(Error::new(ErrorCode::ContractParse, format!("Unknown argument type: <{kind}"))
+                    .token(token));
+            }
+            None => (value, ArgKind::Text),
+        };
 
-        eprintln!("The argument must be explicitly defined as required or optional using ! or ?");
-        exit(2)
+        Ok(Arg {
+            name: name.to_string(),
+            min,
+            max,
+            kind,
+        })
     }
 }
 
@@ -146,16 +557,13 @@ pub struct Flag {
     name: String,
     required: bool,
     required_value: bool,
+    repeatable: bool,
 }
 
 impl PartialEq<String> for Flag {
     fn eq(&self, other: &String) -> bool {
         self.name.eq(other)
     }
-
-    fn ne(&self, other: &String) -> bool {
-        self.name.ne(other)
-    }
 }
 
 impl Flag {
@@ -167,23 +575,53 @@ impl Flag {
         self.required_value
     }
 
+    /// A flag with a value may only be given more than once if it is declared with `*` or `+`.
+    pub fn is_repeatable(&self) -> bool {
+        self.repeatable
+    }
+
     pub fn name(&self) -> &String {
         &self.name
     }
 }
 
+#[cfg(test)]
 impl From<String> for Flag {
     fn from(value: String) -> Self {
+        Flag::from(value.as_str())
+    }
+}
+
+/// For declaring a flag in tests, panics if it is not valid.
+#[cfg(test)]
+impl From<&str> for Flag {
+    fn from(value: &str) -> Self {
+        value.parse().unwrap()
+    }
+}
+
+impl FromStr for Flag {
+    type Err = Error;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
         let required: bool;
+        let repeatable: bool;
         let required_value = value.contains("=");
 
         if value.ends_with("!") {
-            required = true;
+            (required, repeatable) = (true, false);
         } else if value.ends_with("?") {
-            required = false;
+            (required, repeatable) = (false, false);
+        } else if value.ends_with("+") {
+            (required, repeatable) = (true, true);
+        } else if value.ends_with("*") {
+            (required, repeatable) = (false, true);
         } else {
-            println!("The flag must be explicitly defined as required or optional using ! or ?");
-            exit(2)
+            return Err(Error::new(
+                ErrorCode::ContractParse,
+                "The flag must be explicitly defined as required or optional using ! or ?",
+            )
+            .token(value));
         }
 
         let name: String = if required_value {
@@ -192,43 +630,90 @@ impl From<String> for Flag {
             value[..value.len() - 1].to_string()
         };
 
-        Flag {
+        Ok(Flag {
             name,
             required,
             required_value,
-        }
+            repeatable,
+        })
     }
 }
 
-impl From<&str> for Flag {
-    fn from(value: &str) -> Self {
-        let required: bool;
-        let required_value = value.contains("=");
+/// A rule over several flags: `one_of{--wifi,--ethernet}`, `at_most_one_of{--json,--csv}`
+/// or `requires{--password,--ssid}`, where the first flag requires all the others.
+#[derive(PartialEq, Debug, Clone)]
+pub struct FlagGroup {
+    kind: GroupKind,
+    flags: Vec<String>,
+}
 
-        if value.ends_with("!") {
-            required = true;
-        } else if value.ends_with("?") {
-            required = false;
-        } else {
-            println!("The flag must be explicitly defined as required or optional using ! or ?");
-            exit(2)
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum GroupKind {
+    OneOf,
+    AtMostOneOf,
+    Requires,
+}
+
+impl GroupKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            GroupKind::OneOf => "one_of",
+            GroupKind::AtMostOneOf => "at_most_one_of",
+            GroupKind::Requires => "requires",
         }
+    }
+}
 
-        let name: String = if required_value {
-            value[..value.len() - 2].to_string()
-        } else {
-            value[..value.len() - 1].to_string()
+impl FlagGroup {
+    pub fn kind(&self) -> GroupKind {
+        self.kind
+    }
+
+    pub fn flags(&self) -> &Vec<String> {
+        &self.flags
+    }
+}
+
+impl Display for FlagGroup {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}{{{}}}", self.kind.as_str(), self.flags.join(","))
+    }
+}
+
+impl FromStr for FlagGroup {
+    type Err = Error;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let Some((kind, flags)) = value.strip_suffix("}").and_then(|x| x.split_once("{")) else {
+            return Err(Error::new(
+                ErrorCode::ContractParse,
+                "A flag group must be written as kind{--flag,--flag}",
+            )
+            .token(value));
         };
 
-        Flag {
-            name,
-            required,
-            required_value,
-        }
+        let kind = match kind {
+            "one_of" => GroupKind::OneOf,
+            "at_most_one_of" => GroupKind::AtMostOneOf,
+            "requires" => GroupKind::Requires,
+            _ => {
+                return Err(Error::new(ErrorCode::ContractParse, format!("Unknown flag group: {kind}"))
+                    .token(value));
+            }
+        };
+
+        Ok(FlagGroup {
+            kind,
+            flags: flags
+                .split(",")
+                .map(|x| x.trim().to_string())
+                .filter(|x| !x.is_empty())
+                .collect(),
+        })
     }
 }
 
-#[derive(PartialEq, Debug)]
+#[derive(PartialEq, Debug, Clone)]
 pub enum Stdin {
     Required,
     Optional,
@@ -237,20 +722,18 @@ pub enum Stdin {
 
 impl From<String> for Stdin {
     fn from(value: String) -> Self {
-        if value == "stdin!" {
-            return Stdin::Required;
-        }
-
-        if value == "stdin?" {
-            return Stdin::Optional;
-        }
-
-        Stdin::None
+        Stdin::from(value.as_str())
     }
 }
 
 impl From<&str> for Stdin {
     fn from(value: &str) -> Self {
+        // `stdin:pipe!` is as required as `stdin!`, the stream is checked separately.
+        let value = match value.split_once(":") {
+            Some(("stdin", mode)) => format!("stdin{}", &mode[mode.len().saturating_sub(1)..]),
+            _ => value.to_string(),
+        };
+
         if value == "stdin!" {
             return Stdin::Required;
         }
@@ -275,7 +758,7 @@ impl From<Option<&str>> for Stdin {
     }
 }
 
-#[derive(PartialEq, Debug)]
+#[derive(PartialEq, Debug, Clone)]
 pub enum Stdout {
     Required,
     Optional,
@@ -396,11 +879,24 @@ mod test {
                 .unwrap(),
             Contract {
                 stdin: Stdin::Required,
+                stdin_stream: None,
+                stdin_format: None,
                 args: vec![Arg::from("arg?"), Arg::from("arg!"), Arg::from("aboba!")],
                 flags: HashSet::from([Flag::from("--flag!"), Flag::from("--flag2?")]),
                 stdout: Stdout::Optional,
                 error_codes: vec![2, 42, 50],
-                name: "aboba".into()
+                name: "aboba".into(),
+                supervision: None,
+                groups: vec![],
+                pure: false,
+                ttl: None,
+                lock: None,
+                lock_wait: None,
+                version: None,
+                min_version: None,
+                deprecated: None,
+                private: false,
+                state: false,
             }
         );
 
@@ -410,11 +906,24 @@ mod test {
                 .unwrap(),
             Contract {
                 stdin: Stdin::Optional,
+                stdin_stream: None,
+                stdin_format: None,
                 args: vec![Arg::from("arg!"), Arg::from("aboba!")],
                 flags: HashSet::from([Flag::from("--flag!"), Flag::from("--flag2?")]),
                 stdout: Stdout::Required,
                 error_codes: vec![2, 42, 50],
-                name: "name".into()
+                name: "name".into(),
+                supervision: None,
+                groups: vec![],
+                pure: false,
+                ttl: None,
+                lock: None,
+                lock_wait: None,
+                version: None,
+                min_version: None,
+                deprecated: None,
+                private: false,
+                state: false,
             }
         );
 
@@ -424,11 +933,24 @@ mod test {
                 .unwrap(),
             Contract {
                 stdin: Stdin::Required,
+                stdin_stream: None,
+                stdin_format: None,
                 args: vec![Arg::from("arg?"), Arg::from("arg!")],
                 flags: HashSet::from([Flag::from("--flag!"), Flag::from("--flag2!")]),
                 stdout: Stdout::Optional,
                 error_codes: vec![2, 42, 50],
                 name: "aga".into(),
+                supervision: None,
+                groups: vec![],
+                pure: false,
+                ttl: None,
+                lock: None,
+                lock_wait: None,
+                version: None,
+                min_version: None,
+                deprecated: None,
+                private: false,
+                state: false,
             }
         );
 
@@ -438,21 +960,135 @@ mod test {
                 .unwrap(),
             Contract {
                 stdin: Stdin::None,
+                stdin_stream: None,
+                stdin_format: None,
                 args: vec![Arg::from("arg?"), Arg::from("arg!")],
                 flags: HashSet::from([Flag::from("--flag!"), Flag::from("--flag2?")]),
                 stdout: Stdout::None,
                 error_codes: Vec::new(),
-                name: "contract".into()
+                name: "contract".into(),
+                supervision: None,
+                groups: vec![],
+                pure: false,
+                ttl: None,
+                lock: None,
+                lock_wait: None,
+                version: None,
+                min_version: None,
+                deprecated: None,
+                private: false,
+                state: false,
             }
         );
     }
 
+    #[test]
+    fn contract_supervision() {
+        let contract = "backup: arg! @timeout 5m @memory 512M @files 64 [3]"
+            .parse::<Contract>()
+            .unwrap();
+
+        assert_eq!(
+            contract.supervision(),
+            Some(&Supervision {
+                timeout: Some(Duration::from_secs(300)),
+                memory: Some(512 * 1024 * 1024),
+                files: Some(64),
+                ..Supervision::default()
+            })
+        );
+        assert_eq!(contract.error_codes, vec![3]);
+
+        let contract = "info: stdout! @supervised".parse::<Contract>().unwrap();
+        assert_eq!(contract.supervision(), Some(&Supervision::default()));
+
+        let contract = "info: stdout!".parse::<Contract>().unwrap();
+        assert_eq!(contract.supervision(), None);
+
+        assert!("info: @timeout".parse::<Contract>().is_err());
+        assert!("info: @unknown".parse::<Contract>().is_err());
+        assert!("info: stdout! 5m".parse::<Contract>().is_err());
+    }
+
+    #[test]
+    fn contract_files() {
+        let contract = "serve: @files 1024".parse::<Contract>().unwrap();
+        assert_eq!(contract.supervision().unwrap().files, Some(1024));
+
+        assert!("serve: @files 1K".parse::<Contract>().is_err());
+        assert!("serve: @files 5m".parse::<Contract>().is_err());
+    }
+
+    #[test]
+    fn contract_pure() {
+        let contract = "info: stdout! @pure".parse::<Contract>().unwrap();
+        assert!(contract.pure());
+        assert_eq!(contract.ttl(), None);
+        assert_eq!(contract.supervision(), Some(&Supervision::default()));
+
+        let contract = "info: stdout! @ttl 1h".parse::<Contract>().unwrap();
+        assert!(contract.pure());
+        assert_eq!(contract.ttl(), Some(Duration::from_secs(3600)));
+    }
+
+    #[test]
+    fn contract_stdin_format() {
+        let contract = "import: stdin! @stdin_format jsonl".parse::<Contract>().unwrap();
+        assert_eq!(contract.stdin_format(), Some(Format::Jsonl));
+        assert_eq!(contract.supervision(), Some(&Supervision::default()));
+
+        assert!("import: stdin! @stdin_format yaml".parse::<Contract>().is_err());
+        assert!("import: stdin! @stdin_format".parse::<Contract>().is_err());
+        assert!("import: stdin! json".parse::<Contract>().is_err());
+    }
+
+    #[test]
+    fn contract_lock() {
+        let contract = "backup: @lock".parse::<Contract>().unwrap();
+        assert_eq!(contract.lock(), Some(LockScope::Method));
+        assert_eq!(contract.lock_wait(), None);
+
+        let contract = "upgrade: @lock(object) @lock_wait 30s -> (pkg!)"
+            .parse::<Contract>()
+            .unwrap();
+        assert_eq!(contract.lock(), Some(LockScope::Object));
+        assert_eq!(contract.lock_wait(), Some(LockWait::Timeout(Duration::from_secs(30))));
+        assert_eq!(contract.args().len(), 1);
+
+        let contract = "backup: @lock (path!) @lock_wait fail".parse::<Contract>().unwrap();
+        assert_eq!(contract.lock(), Some(LockScope::Method));
+        assert_eq!(contract.lock_wait(), Some(LockWait::FailFast));
+        assert_eq!(contract.args().len(), 1);
+
+        assert!("backup: @lock(object".parse::<Contract>().is_err());
+        assert!("backup: @lock_wait soon".parse::<Contract>().is_err());
+    }
+
     #[test]
     fn arg() {
-        assert!(Arg::from("aboba!").required);
-        assert!(!Arg::from("aboba?").required);
-        assert!(Arg::from("aboba!".to_string()).required);
-        assert!(!Arg::from("aboba?".to_string()).required);
+        assert!(Arg::from("aboba!").is_required());
+        assert!(!Arg::from("aboba?").is_required());
+        assert!(Arg::from("aboba!".to_string()).is_required());
+        assert!(!Arg::from("aboba?".to_string()).is_required());
+    }
+
+    #[test]
+    fn typed_arg() {
+        let arg = Arg::from("count<int>!");
+        assert_eq!(arg.name, "count");
+        assert_eq!(arg.kind, ArgKind::Int);
+        assert!(arg.is_required());
+
+        let arg = Arg::from("ratio<num>?");
+        assert_eq!(arg.name, "ratio");
+        assert_eq!(arg.kind, ArgKind::Num);
+        assert!(!arg.is_required());
+
+        assert_eq!(Arg::from("ssid!").kind, ArgKind::Text);
+        assert!(ArgKind::Int.accepts("-5"));
+        assert!(!ArgKind::Int.accepts("5.5"));
+        assert!(ArgKind::Num.accepts("-5.5"));
+        assert!(!ArgKind::Num.accepts("inf"));
     }
 
     #[test]
@@ -498,6 +1134,86 @@ mod test {
         assert_eq!("-a", flag.name);
     }
 
+    #[test]
+    fn variadic_arg() {
+        let arg = Arg::from("files...!");
+        assert_eq!((arg.name.as_str(), arg.min, arg.max), ("files", 1, None));
+
+        let arg = Arg::from("files...?");
+        assert_eq!((arg.name.as_str(), arg.min, arg.max), ("files", 0, None));
+
+        let arg = Arg::from("files{2,5}");
+        assert_eq!((arg.name.as_str(), arg.min, arg.max), ("files", 2, Some(5)));
+
+        let arg = Arg::from("files{2,}");
+        assert_eq!((arg.min, arg.max), (2, None));
+
+        let arg = Arg::from("files{,3}");
+        assert_eq!((arg.min, arg.max), (0, Some(3)));
+
+        let arg = Arg::from("points<int>{2}");
+        assert_eq!((arg.name.as_str(), arg.min, arg.max), ("points", 2, Some(2)));
+        assert_eq!(arg.kind, ArgKind::Int);
+
+        let contract = "tag: tag! files...! --tag=* --label=+"
+            .parse::<Contract>()
+            .unwrap();
+        assert_eq!(contract.min_args(), 2);
+        assert_eq!(contract.max_args(), None);
+        assert!(contract.flags().iter().all(|x| x.is_repeatable()));
+
+        let contract = "pair: a! b? c{1,3}".parse::<Contract>().unwrap();
+        assert_eq!(contract.min_args(), 2);
+        assert_eq!(contract.max_args(), Some(5));
+
+        assert!("bad: files{3,2}".parse::<Contract>().is_err());
+    }
+
+    #[test]
+    fn flag_group() {
+        let contract = "connect: --wifi? --ethernet? --ssid=? --password=? one_of{--wifi, --ethernet} requires{--password,--ssid}"
+            .parse::<Contract>()
+            .unwrap();
+
+        assert_eq!(
+            contract.groups(),
+            &vec![
+                FlagGroup {
+                    kind: GroupKind::OneOf,
+                    flags: vec!["--wifi".into(), "--ethernet".into()],
+                },
+                FlagGroup {
+                    kind: GroupKind::Requires,
+                    flags: vec!["--password".into(), "--ssid".into()],
+                },
+            ]
+        );
+        assert_eq!(contract.groups()[0].to_string(), "one_of{--wifi,--ethernet}");
+
+        assert!("bad: --a? one_of{--a,--b}".parse::<Contract>().is_err());
+        assert!("bad: --a? one_of{--a}".parse::<Contract>().is_err());
+        assert!("bad: --a? --b? some_of{--a,--b}".parse::<Contract>().is_err());
+    }
+
+    #[test]
+    fn repeatable_flag() {
+        let flag = Flag::from("--tag=*");
+
+        assert!(!flag.required);
+        assert!(flag.repeatable);
+        assert!(flag.required_value);
+        assert_eq!("--tag", flag.name);
+
+        let flag = Flag::from("-v+");
+
+        assert!(flag.required);
+        assert!(flag.repeatable);
+        assert!(!flag.required_value);
+        assert_eq!("-v", flag.name);
+
+        assert!(!Flag::from("--tag=?").repeatable);
+    }
+
     #[test]
     #[should_panic]
     fn flag_from_str() {
@@ -510,6 +1226,15 @@ mod test {
         let _ = Flag::from("aboba".to_string());
     }
 
+    #[test]
+    fn invalid_parts() {
+        assert_eq!("aboba".parse::<Flag>().unwrap_err().code(), ErrorCode::ContractParse);
+        assert!("aboba".parse::<Arg>().is_err());
+        assert!("size<float>!".parse::<Arg>().is_err());
+        assert!("any_of{--wifi,--ethernet}".parse::<FlagGroup>().is_err());
+        assert!("one_of{--wifi,--ethernet}".parse::<FlagGroup>().is_ok());
+    }
+
     #[test]
     fn stdin() {
         assert_eq!(Stdin::from("stdin!"), Stdin::Required);
@@ -528,6 +1253,19 @@ mod test {
         assert_eq!(Stdin::from(Some(String::default())), Stdin::None);
     }
 
+    #[test]
+    fn stdin_stream() {
+        assert_eq!(Stdin::from("stdin:pipe!"), Stdin::Required);
+        assert_eq!(Stdin::from("stdin:tty?"), Stdin::Optional);
+
+        let contract = "read: stdin:pipe! stdout?".parse::<Contract>().unwrap();
+        assert_eq!(contract.stdin(), &Stdin::Required);
+        assert_eq!(contract.stdin_stream(), Some(Stream::Pipe));
+
+        let contract = "read: stdin! stdout?".parse::<Contract>().unwrap();
+        assert_eq!(contract.stdin_stream(), None);
+    }
+
     #[test]
     fn stdout() {
         assert_eq!(Stdout::from("stdout!"), Stdout::Required);
@@ -546,4 +1284,37 @@ mod test {
         assert_eq!(Stdout::from(Some("")), Stdout::None);
         assert_eq!(Stdout::from(Some(String::default())), Stdout::None);
     }
+
+    #[test]
+    fn versioning() {
+        let contract = r#"connect: ssid! @version 2 @deprecated "use \"net.wifi.join\"""#
+            .parse::<Contract>()
+            .unwrap();
+        assert_eq!(contract.version(), 2);
+        assert_eq!(contract.deprecated(), Some(&r#"use "net.wifi.join""#.to_string()));
+
+        let contract = "connect: ssid! @deprecated @min_version 3".parse::<Contract>().unwrap();
+        assert_eq!(contract.version(), 1);
+        assert_eq!(contract.min_version(), Some(3));
+        assert_eq!(contract.deprecated(), Some(&String::new()));
+
+        assert!(contract.same_signature(&"connect: ssid!".parse::<Contract>().unwrap()));
+        assert!(!contract.same_signature(&"connect: ssid?".parse::<Contract>().unwrap()));
+
+        assert!("bad: @version".parse::<Contract>().is_err());
+        assert!(r#"bad: "text""#.parse::<Contract>().is_err());
+    }
+
+    #[test]
+    fn private() {
+        assert!("helper: private stdout?".parse::<Contract>().unwrap().private());
+        assert!(!"helper: stdout?".parse::<Contract>().unwrap().private());
+        assert!("helper: public stdout?".parse::<Contract>().is_err());
+    }
+
+    #[test]
+    fn state() {
+        assert!("count: stdout! @state".parse::<Contract>().unwrap().state());
+        assert!(!"count: stdout!".parse::<Contract>().unwrap().state());
+    }
 }
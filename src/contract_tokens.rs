@@ -1,26 +1,48 @@
 use logos::Logos;
 
+#[allow(clippy::upper_case_acronyms)]
 #[derive(Logos, Debug, PartialEq, Clone)]
 #[logos(skip r"[ \t\n\f\r]+")]
 pub enum ContractTokens {
     #[token("#>>>")]
     Start,
 
-    #[regex(r"stdin[!?]")]
+    #[regex(r"stdin(:(pipe|file|tty))?[!?]")]
     Stdin,
 
     #[regex(r"stdout[!?]")]
     Stdout,
 
-    #[regex(r"\w+[?!]")]
+    #[regex(r"\w+(<(int|num)>)?[?!]")]
     Arg,
 
+    #[regex(r"\w+(<(int|num)>)?\.\.\.[?!]")]
+    VariadicArg,
+
+    #[regex(r"\w+(<(int|num)>)?\{\d*(,\d*)?\}")]
+    RangeArg,
+
     #[regex(r"--?[a-zA-Z]\w*=?[?!]")]
     Flag,
 
+    #[regex(r"--?[a-zA-Z]\w*=?[*+]")]
+    RepeatableFlag,
+
+    #[regex(r"(one_of|at_most_one_of|requires)\{[^}]*\}")]
+    Group,
+
     #[regex(r"\d+")]
     Number,
 
+    #[regex(r"\d+[smhdKMG]")]
+    Quantity,
+
+    #[regex(r"@\w+")]
+    Annotation,
+
+    #[regex(r#""([^"\\]|\\.)*""#)]
+    Str,
+
     #[token("->")]
     Arrow,
 
@@ -41,6 +63,9 @@ pub enum ContractTokens {
 
     #[regex(r"\w+:")]
     Name,
+
+    #[regex(r"[a-zA-Z]\w*")]
+    Word,
 }
 
 #[cfg(test)]
@@ -251,4 +276,116 @@ mod test {
         assert_eq!(lex.next(), Some(Ok(ContractTokens::Flag)));
         assert_eq!(lex.slice(), "--flag=?");
     }
+
+    #[test]
+    fn stdin_format() {
+        let mut lex = ContractTokens::lexer("@stdin_format json");
+
+        assert_eq!(lex.next(), Some(Ok(ContractTokens::Annotation)));
+        assert_eq!(lex.slice(), "@stdin_format");
+
+        assert_eq!(lex.next(), Some(Ok(ContractTokens::Word)));
+        assert_eq!(lex.slice(), "json");
+    }
+
+    #[test]
+    fn supervision() {
+        let mut lex = ContractTokens::lexer("@supervised @timeout 5m @memory 512M @files 64");
+
+        assert_eq!(lex.next(), Some(Ok(ContractTokens::Annotation)));
+        assert_eq!(lex.slice(), "@supervised");
+
+        assert_eq!(lex.next(), Some(Ok(ContractTokens::Annotation)));
+        assert_eq!(lex.slice(), "@timeout");
+
+        assert_eq!(lex.next(), Some(Ok(ContractTokens::Quantity)));
+        assert_eq!(lex.slice(), "5m");
+
+        assert_eq!(lex.next(), Some(Ok(ContractTokens::Annotation)));
+        assert_eq!(lex.slice(), "@memory");
+
+        assert_eq!(lex.next(), Some(Ok(ContractTokens::Quantity)));
+        assert_eq!(lex.slice(), "512M");
+
+        assert_eq!(lex.next(), Some(Ok(ContractTokens::Annotation)));
+        assert_eq!(lex.slice(), "@files");
+
+        assert_eq!(lex.next(), Some(Ok(ContractTokens::Number)));
+        assert_eq!(lex.slice(), "64");
+    }
+
+    #[test]
+    fn typed_arguments() {
+        let mut lex = ContractTokens::lexer("count<int>! ratio<num>? name<str>!");
+
+        assert_eq!(lex.next(), Some(Ok(ContractTokens::Arg)));
+        assert_eq!(lex.slice(), "count<int>!");
+
+        assert_eq!(lex.next(), Some(Ok(ContractTokens::Arg)));
+        assert_eq!(lex.slice(), "ratio<num>?");
+
+        assert_ne!(lex.next(), Some(Ok(ContractTokens::Arg)));
+    }
+
+    #[test]
+    fn variadics() {
+        let mut lex = ContractTokens::lexer("files...! rest<int>...? pair{2,5} some{,3} --tag=* -v+");
+
+        assert_eq!(lex.next(), Some(Ok(ContractTokens::VariadicArg)));
+        assert_eq!(lex.slice(), "files...!");
+
+        assert_eq!(lex.next(), Some(Ok(ContractTokens::VariadicArg)));
+        assert_eq!(lex.slice(), "rest<int>...?");
+
+        assert_eq!(lex.next(), Some(Ok(ContractTokens::RangeArg)));
+        assert_eq!(lex.slice(), "pair{2,5}");
+
+        assert_eq!(lex.next(), Some(Ok(ContractTokens::RangeArg)));
+        assert_eq!(lex.slice(), "some{,3}");
+
+        assert_eq!(lex.next(), Some(Ok(ContractTokens::RepeatableFlag)));
+        assert_eq!(lex.slice(), "--tag=*");
+
+        assert_eq!(lex.next(), Some(Ok(ContractTokens::RepeatableFlag)));
+        assert_eq!(lex.slice(), "-v+");
+    }
+
+    #[test]
+    fn flag_groups() {
+        let mut lex = ContractTokens::lexer("one_of{--wifi,--ethernet} requires{--password, --ssid}");
+
+        assert_eq!(lex.next(), Some(Ok(ContractTokens::Group)));
+        assert_eq!(lex.slice(), "one_of{--wifi,--ethernet}");
+
+        assert_eq!(lex.next(), Some(Ok(ContractTokens::Group)));
+        assert_eq!(lex.slice(), "requires{--password, --ssid}");
+    }
+
+    #[test]
+    fn stdin_sources() {
+        let mut lex = ContractTokens::lexer("stdin:pipe! stdin:tty?");
+
+        assert_eq!(lex.next(), Some(Ok(ContractTokens::Stdin)));
+        assert_eq!(lex.slice(), "stdin:pipe!");
+
+        assert_eq!(lex.next(), Some(Ok(ContractTokens::Stdin)));
+        assert_eq!(lex.slice(), "stdin:tty?");
+    }
+
+    #[test]
+    fn versions() {
+        let mut lex = ContractTokens::lexer(r#"@version 2 @deprecated "use \"join\"""#);
+
+        assert_eq!(lex.next(), Some(Ok(ContractTokens::Annotation)));
+        assert_eq!(lex.slice(), "@version");
+
+        assert_eq!(lex.next(), Some(Ok(ContractTokens::Number)));
+        assert_eq!(lex.slice(), "2");
+
+        assert_eq!(lex.next(), Some(Ok(ContractTokens::Annotation)));
+        assert_eq!(lex.slice(), "@deprecated");
+
+        assert_eq!(lex.next(), Some(Ok(ContractTokens::Str)));
+        assert_eq!(lex.slice(), r#""use \"join\"""#);
+    }
 }
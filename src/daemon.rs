@@ -0,0 +1,405 @@
+use crate::commands::methods;
+use crate::contract::{Contract, Stdin, Stdout};
+use crate::error::{Error, ErrorCode};
+use crate::handlers::{Handle, ResolveHandler, ValidationHandler};
+use crate::index;
+use crate::method::Method;
+use crate::supervisor;
+use crate::watch::Watcher;
+use serde::Deserialize;
+use serde_json::{Value, json};
+use std::io::{BufRead, BufReader, Read, Write};
+use std::os::unix::fs::FileTypeExt;
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::{Arc, Mutex};
+use std::{env, fs, io, thread};
+
+/// JSON-RPC error codes, see https://www.jsonrpc.org/specification#error_object
+const PARSE_ERROR: i32 = -32700;
+const METHOD_NOT_FOUND: i32 = -32601;
+const INVALID_PARAMS: i32 = -32602;
+/// A call refused by irnix. The irnix error is in `data`.
+const CALL_ERROR: i32 = -32000;
+
+#[derive(Deserialize)]
+struct Request {
+    #[serde(default)]
+    id: Value,
+    method: String,
+    #[serde(default)]
+    params: Value,
+}
+
+#[derive(Deserialize, Default)]
+struct ListParams {
+    prefix: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct CallParams {
+    method: String,
+    #[serde(default)]
+    args: Vec<String>,
+    stdin: Option<String>,
+}
+
+type Writer = Arc<Mutex<UnixStream>>;
+
+/// Serves newline delimited JSON-RPC 2.0 on a Unix socket.
+///
+/// `list`, `describe` and `validate` are answered by the daemon. `execute` runs
+/// `irnix e` and streams its output as `output` notifications before the result.
+///
+/// Unless `cached` is false, the namespace index is rebuilt whenever inotify reports a change.
+pub fn serve(namespace: PathBuf, socket: &Path, cached: bool) -> Result<(), Error> {
+    if let Some(dir) = socket.parent() {
+        fs::create_dir_all(dir).map_err(|err| Error::io(dir, err))?;
+    }
+    remove_stale(socket).map_err(|err| Error::io(socket, err))?;
+
+    // The socket is created with mode 0600, there is no moment others could connect to it.
+    // No other thread runs yet to create files under the umask.
+    let umask = unsafe { libc::umask(0o177) };
+    let listener = UnixListener::bind(socket);
+    unsafe { libc::umask(umask) };
+    let listener = listener.map_err(|err| Error::io(socket, err))?;
+
+    if cached {
+        index::set(index::load(&namespace).ok());
+
+        let watcher = Watcher::new()?;
+        watcher.watch_tree(&namespace)?;
+        let watched = namespace.clone();
+        thread::spawn(move || {
+            while watcher.wait().is_ok() {
+                index::set(index::refresh(&watched).ok());
+                let _ = watcher.watch_tree(&watched);
+            }
+        });
+    }
+
+    for stream in listener.incoming() {
+        let stream = match stream {
+            Ok(stream) => stream,
+            Err(err) => {
+                eprintln!("Failed to accept a connection: {err}");
+                continue;
+            }
+        };
+
+        let namespace = namespace.clone();
+        thread::spawn(move || {
+            if let Err(err) = connection(stream, &namespace, cached) {
+                eprintln!("Connection closed: {err}");
+            }
+        });
+    }
+
+    Ok(())
+}
+
+/// Removes the socket left by a daemon that is gone. Anything else at the path is refused.
+fn remove_stale(socket: &Path) -> io::Result<()> {
+    let metadata = match fs::symlink_metadata(socket) {
+        Ok(metadata) => metadata,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(()),
+        Err(err) => return Err(err),
+    };
+
+    if !metadata.file_type().is_socket() {
+        return Err(io::Error::new(io::ErrorKind::AlreadyExists, "the path exists and is not a socket"));
+    }
+    if UnixStream::connect(socket).is_ok() {
+        return Err(io::Error::new(io::ErrorKind::AddrInUse, "another daemon listens on the socket"));
+    }
+
+    fs::remove_file(socket)
+}
+
+fn connection(stream: UnixStream, namespace: &Path, cached: bool) -> io::Result<()> {
+    let writer: Writer = Arc::new(Mutex::new(stream.try_clone()?));
+
+    for line in BufReader::new(stream).lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let request = match serde_json::from_str::<Request>(&line) {
+            Ok(request) => request,
+            Err(err) => {
+                send(&writer, failure(Value::Null, PARSE_ERROR, err.to_string(), None))?;
+                continue;
+            }
+        };
+
+        let response = match handle(&request, namespace, cached, &writer) {
+            Ok(result) => json!({"jsonrpc": "2.0", "id": request.id, "result": result}),
+            Err((code, message, data)) => failure(request.id, code, message, data),
+        };
+        send(&writer, response)?;
+    }
+
+    Ok(())
+}
+
+type Failure = (i32, String, Option<Value>);
+
+fn handle(request: &Request, namespace: &Path, cached: bool, writer: &Writer) -> Result<Value, Failure> {
+    let namespace = namespace.to_path_buf();
+
+    match request.method.as_str() {
+        "list" => {
+            let params = params::<ListParams>(&request.params)?;
+            let methods = match index::current() {
+                Some(index) => index.methods().clone(),
+                None => methods::collect(&namespace).map_err(refused)?,
+            };
+
+            Ok(json!(
+                methods
+                    .into_iter()
+                    .filter(|x| params.prefix.as_ref().is_none_or(|prefix| {
+                        x == prefix || x.starts_with(&format!("{prefix}."))
+                    }))
+                    .collect::<Vec<_>>()
+            ))
+        }
+        "describe" => {
+            let params = params::<CallParams>(&request.params)?;
+            let mut method = Method::new(params.method, vec![], namespace).map_err(refused)?;
+            ResolveHandler::handle(&mut method).map_err(refused)?;
+
+            Ok(method.contract().map(describe).unwrap_or(Value::Null))
+        }
+        "validate" => {
+            let params = params::<CallParams>(&request.params)?;
+            let mut method = Method::new(params.method, params.args, namespace).map_err(refused)?;
+            ValidationHandler::handle(&mut method).map_err(refused)?;
+
+            Ok(json!({"valid": true, "parsed": method.parsed}))
+        }
+        "execute" => {
+            let params = params::<CallParams>(&request.params)?;
+            execute(params, &namespace, cached, &request.id, writer)
+        }
+        _ => Err((
+            METHOD_NOT_FOUND,
+            format!("Unknown method: {}", request.method),
+            None,
+        )),
+    }
+}
+
+fn execute(
+    params: CallParams,
+    namespace: &Path,
+    cached: bool,
+    id: &Value,
+    writer: &Writer,
+) -> Result<Value, Failure> {
+    let spawn_error = |err: io::Error| refused(Error::new(ErrorCode::Exec, format!("Spawn failed: {err}")));
+
+    let mut child = Command::new(env::current_exe().map_err(spawn_error)?)
+        .args((!cached).then_some("--no-cache"))
+        .args(["--error-format", "json", "execute", "--namespace"])
+        .arg(namespace)
+        .arg("--")
+        .arg(&params.method)
+        .args(&params.args)
+        .stdin(if params.stdin.is_some() { Stdio::piped() } else { Stdio::null() })
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(spawn_error)?;
+
+    if let (Some(mut stdin), Some(input)) = (child.stdin.take(), params.stdin) {
+        thread::spawn(move || stdin.write_all(input.as_bytes()));
+    }
+
+    let streams = [
+        child.stdout.take().map(|x| Box::new(x) as Box<dyn Read + Send>),
+        child.stderr.take().map(|x| Box::new(x) as Box<dyn Read + Send>),
+    ];
+    let forwarders = streams
+        .into_iter()
+        .zip(["stdout", "stderr"])
+        .filter_map(|(stream, name)| {
+            let (writer, id) = (writer.clone(), id.clone());
+            stream.map(|stream| thread::spawn(move || forward(stream, name, &id, &writer)))
+        })
+        .collect::<Vec<_>>();
+
+    for forwarder in forwarders {
+        let _ = forwarder.join();
+    }
+
+    let status = child.wait().map_err(spawn_error)?;
+    Ok(json!({"exit_code": supervisor::exit_code(status)}))
+}
+
+/// Sends the output of a call as `{"method": "output", "params": {"id", "stream", "data"}}`.
+fn forward(mut stream: Box<dyn Read + Send>, name: &str, id: &Value, writer: &Writer) -> io::Result<()> {
+    let mut buffer = [0; 8192];
+    let mut pending = Vec::new();
+
+    loop {
+        let read = stream.read(&mut buffer)?;
+        pending.extend_from_slice(&buffer[..read]);
+
+        let data = match read {
+            0 if pending.is_empty() => return Ok(()),
+            0 => String::from_utf8_lossy(&std::mem::take(&mut pending)).to_string(),
+            _ => decode(&mut pending),
+        };
+        if data.is_empty() {
+            continue;
+        }
+
+        send(
+            writer,
+            json!({
+                "jsonrpc": "2.0",
+                "method": "output",
+                "params": {
+                    "id": id,
+                    "stream": name,
+                    "data": data,
+                },
+            }),
+        )?;
+    }
+}
+
+/// Takes the text read so far out of `pending`. A character split across reads stays in
+/// `pending` until the rest of it arrives, invalid bytes are replaced.
+fn decode(pending: &mut Vec<u8>) -> String {
+    let incomplete = (1..=pending.len().min(3))
+        .find(|&n| {
+            std::str::from_utf8(&pending[pending.len() - n..])
+                .is_err_and(|x| x.valid_up_to() == 0 && x.error_len().is_none())
+        })
+        .unwrap_or(0);
+
+    let tail = pending.split_off(pending.len() - incomplete);
+    String::from_utf8_lossy(&std::mem::replace(pending, tail)).to_string()
+}
+
+fn describe(contract: &Contract) -> Value {
+    let stream = |required: bool, optional: bool| match (required, optional) {
+        (true, _) => "required",
+        (_, true) => "optional",
+        _ => "none",
+    };
+
+    let mut flags = contract.flags().into_iter().collect::<Vec<_>>();
+    flags.sort_by(|a, b| a.name().cmp(b.name()));
+
+    json!({
+        "name": contract.name(),
+        "stdin": stream(contract.stdin() == &Stdin::Required, contract.stdin() == &Stdin::Optional),
+        "stdout": stream(contract.stdout() == &Stdout::Required, contract.stdout() == &Stdout::Optional),
+        "args": contract.args().iter().map(|x| json!({
+            "name": x.name(),
+            "min": x.min(),
+            "max": x.max(),
+            "kind": x.kind().as_str(),
+        })).collect::<Vec<_>>(),
+        "flags": flags.iter().map(|x| json!({
+            "name": x.name(),
+            "required": x.is_required(),
+            "value": x.required_value(),
+            "repeatable": x.is_repeatable(),
+        })).collect::<Vec<_>>(),
+        "groups": contract.groups().iter().map(|x| x.to_string()).collect::<Vec<_>>(),
+        "error_codes": contract.error_codes(),
+        "pure": contract.pure(),
+        "version": contract.version(),
+        "deprecated": contract.deprecated(),
+    })
+}
+
+fn params<T: for<'a> Deserialize<'a>>(params: &Value) -> Result<T, Failure> {
+    serde_json::from_value(params.clone()).map_err(|err| (INVALID_PARAMS, err.to_string(), None))
+}
+
+fn refused(error: Error) -> Failure {
+    (
+        CALL_ERROR,
+        error.to_string(),
+        serde_json::to_value(&error).ok(),
+    )
+}
+
+fn failure(id: Value, code: i32, message: String, data: Option<Value>) -> Value {
+    let mut error = json!({"code": code, "message": message});
+    if let Some(data) = data {
+        error["data"] = data;
+    }
+
+    json!({"jsonrpc": "2.0", "id": id, "error": error})
+}
+
+fn send(writer: &Writer, value: Value) -> io::Result<()> {
+    let mut line = value.to_string();
+    line.push('\n');
+    writer.lock().unwrap().write_all(line.as_bytes())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn describe_contract() {
+        let contract = "connect: stdin? ssid! --band=? [3]".parse::<Contract>().unwrap();
+
+        assert_eq!(
+            describe(&contract),
+            json!({
+                "name": "connect",
+                "stdin": "optional",
+                "stdout": "none",
+                "args": [{"name": "ssid", "min": 1, "max": 1, "kind": "text"}],
+                "flags": [{"name": "--band", "required": false, "value": true, "repeatable": false}],
+                "groups": [],
+                "error_codes": [3],
+                "pure": false,
+                "version": 1,
+                "deprecated": null,
+            })
+        );
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!(
+            failure(json!(1), METHOD_NOT_FOUND, "Unknown method: x".into(), None),
+            json!({"jsonrpc": "2.0", "id": 1, "error": {"code": -32601, "message": "Unknown method: x"}})
+        );
+
+        let (code, _, data) = refused(Error::new(ErrorCode::MissingFlag, "missing"));
+        assert_eq!(code, CALL_ERROR);
+        assert_eq!(data.unwrap()["code"], "E_MISSING_FLAG");
+    }
+
+    #[test]
+    fn split_characters() {
+        let text = "сеть ✓ 📶".as_bytes();
+        let mut pending = Vec::new();
+        let mut decoded = String::new();
+
+        for chunk in text.chunks(3) {
+            pending.extend_from_slice(chunk);
+            decoded += &decode(&mut pending);
+        }
+        assert_eq!(decoded, "сеть ✓ 📶");
+        assert!(pending.is_empty());
+
+        let mut pending = b"a\xff\xf0\x9f".to_vec();
+        assert_eq!(decode(&mut pending), "a\u{fffd}");
+        assert_eq!(pending, b"\xf0\x9f");
+    }
+}
@@ -0,0 +1,243 @@
+use crate::audit;
+use crate::supervisor::TIMEOUT_EXIT_CODE;
+use clap::ValueEnum;
+use serde::{Serialize, Serializer};
+use std::fmt::{Display, Formatter};
+use std::process::exit;
+use std::sync::OnceLock;
+
+static FORMAT: OnceLock<ErrorFormat> = OnceLock::new();
+
+#[derive(ValueEnum, Clone, Copy, PartialEq, Debug, Default)]
+pub enum ErrorFormat {
+    #[default]
+    Text,
+    Json,
+}
+
+/// Sets how [`Error::exit`] reports failures for the rest of the process.
+pub fn set_format(format: ErrorFormat) {
+    let _ = FORMAT.set(format);
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum ErrorCode {
+    InvalidName,
+    ObjectNotFound,
+    Io,
+    ContractParse,
+    InterfaceContractMissing,
+    InterfaceLayout,
+    ObjectContractMissing,
+    InterfaceMismatch,
+    InterfaceVersion,
+    MethodNotInInterface,
+    PrivateMethod,
+    UnknownFlag,
+    UnexpectedFlagValue,
+    MissingFlagValue,
+    MissingArguments,
+    TooManyArguments,
+    MissingFlag,
+    RepeatedFlag,
+    FlagGroup,
+    InvalidArgument,
+    StdinRequired,
+    StdinUnexpected,
+    StdinFormat,
+    StdoutUnexpected,
+    Deprecated,
+    Uncontracted,
+    Hook,
+    Init,
+    TestFile,
+    MockMismatch,
+    ContractExists,
+    Locked,
+    Remote,
+    UndeclaredExitCode,
+    Exec,
+    Timeout,
+    AuditLogDisabled,
+    CheckFailed,
+    TestFailed,
+    FuzzFailed,
+    NotObserved,
+}
+
+impl ErrorCode {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ErrorCode::InvalidName => "E_INVALID_NAME",
+            ErrorCode::ObjectNotFound => "E_OBJECT_NOT_FOUND",
+            ErrorCode::Io => "E_IO",
+            ErrorCode::ContractParse => "E_CONTRACT_PARSE",
+            ErrorCode::InterfaceContractMissing => "E_INTERFACE_CONTRACT_MISSING",
+            ErrorCode::InterfaceLayout => "E_INTERFACE_LAYOUT",
+            ErrorCode::ObjectContractMissing => "E_OBJECT_CONTRACT_MISSING",
+            ErrorCode::InterfaceMismatch => "E_INTERFACE_MISMATCH",
+            ErrorCode::InterfaceVersion => "E_INTERFACE_VERSION",
+            ErrorCode::MethodNotInInterface => "E_METHOD_NOT_IN_INTERFACE",
+            ErrorCode::PrivateMethod => "E_PRIVATE_METHOD",
+            ErrorCode::UnknownFlag => "E_UNKNOWN_FLAG",
+            ErrorCode::UnexpectedFlagValue => "E_UNEXPECTED_FLAG_VALUE",
+            ErrorCode::MissingFlagValue => "E_MISSING_FLAG_VALUE",
+            ErrorCode::MissingArguments => "E_MISSING_ARGUMENTS",
+            ErrorCode::TooManyArguments => "E_TOO_MANY_ARGUMENTS",
+            ErrorCode::MissingFlag => "E_MISSING_FLAG",
+            ErrorCode::RepeatedFlag => "E_REPEATED_FLAG",
+            ErrorCode::FlagGroup => "E_FLAG_GROUP",
+            ErrorCode::InvalidArgument => "E_INVALID_ARGUMENT",
+            ErrorCode::StdinRequired => "E_STDIN_REQUIRED",
+            ErrorCode::StdinUnexpected => "E_STDIN_UNEXPECTED",
+            ErrorCode::StdinFormat => "E_STDIN_FORMAT",
+            ErrorCode::StdoutUnexpected => "E_STDOUT_UNEXPECTED",
+            ErrorCode::Deprecated => "E_DEPRECATED",
+            ErrorCode::Uncontracted => "E_UNCONTRACTED",
+            ErrorCode::Hook => "E_HOOK_FAILED",
+            ErrorCode::Init => "E_INIT_FAILED",
+            ErrorCode::TestFile => "E_TEST_FILE",
+            ErrorCode::MockMismatch => "E_MOCK_MISMATCH",
+            ErrorCode::ContractExists => "E_CONTRACT_EXISTS",
+            ErrorCode::Locked => "E_LOCKED",
+            ErrorCode::Remote => "E_REMOTE",
+            ErrorCode::UndeclaredExitCode => "E_UNDECLARED_EXIT_CODE",
+            ErrorCode::Exec => "E_EXEC",
+            ErrorCode::Timeout => "E_TIMEOUT",
+            ErrorCode::AuditLogDisabled => "E_AUDIT_LOG_DISABLED",
+            ErrorCode::CheckFailed => "E_CHECK_FAILED",
+            ErrorCode::TestFailed => "E_TEST_FAILED",
+            ErrorCode::FuzzFailed => "E_FUZZ_FAILED",
+            ErrorCode::NotObserved => "E_NOT_OBSERVED",
+        }
+    }
+
+    /// A call that breaks the contract exits with 2, a broken namespace or environment with 1.
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            ErrorCode::InvalidName
+            | ErrorCode::MethodNotInInterface
+            | ErrorCode::PrivateMethod
+            | ErrorCode::UnknownFlag
+            | ErrorCode::UnexpectedFlagValue
+            | ErrorCode::MissingFlagValue
+            | ErrorCode::MissingArguments
+            | ErrorCode::TooManyArguments
+            | ErrorCode::MissingFlag
+            | ErrorCode::RepeatedFlag
+            | ErrorCode::FlagGroup
+            | ErrorCode::InvalidArgument
+            | ErrorCode::StdinRequired
+            | ErrorCode::StdinUnexpected
+            | ErrorCode::StdinFormat
+            | ErrorCode::StdoutUnexpected
+            | ErrorCode::Deprecated => 2,
+            ErrorCode::Timeout => TIMEOUT_EXIT_CODE,
+            _ => 1,
+        }
+    }
+}
+
+impl Serialize for ErrorCode {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.as_str())
+    }
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct Error {
+    code: ErrorCode,
+    message: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    method: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    contract: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    token: Option<String>,
+    exit_code: i32,
+}
+
+impl Error {
+    pub fn new(code: ErrorCode, message: impl Into<String>) -> Error {
+        Error {
+            code,
+            message: message.into(),
+            method: None,
+            contract: None,
+            token: None,
+            exit_code: code.exit_code(),
+        }
+    }
+
+    pub fn io(context: impl std::fmt::Debug, err: std::io::Error) -> Error {
+        Error::new(ErrorCode::Io, format!("IO Error: {:?} : {err}", context))
+    }
+
+    #[allow(dead_code)]
+    pub fn code(&self) -> ErrorCode {
+        self.code
+    }
+
+    /// Dotted name of the called method. Only set if it is not known yet.
+    pub fn method(mut self, method: impl Into<String>) -> Error {
+        self.method.get_or_insert(method.into());
+        self
+    }
+
+    pub fn contract(mut self, contract: impl Into<String>) -> Error {
+        self.contract = Some(contract.into());
+        self
+    }
+
+    pub fn token(mut self, token: impl Into<String>) -> Error {
+        self.token = Some(token.into());
+        self
+    }
+
+    /// Reports the error in the selected format, records it in the audit log and exits.
+    pub fn exit(self) -> ! {
+        match FORMAT.get().copied().unwrap_or_default() {
+            ErrorFormat::Text => eprintln!("{self}"),
+            ErrorFormat::Json => eprintln!("{}", serde_json::to_string(&self).unwrap()),
+        }
+
+        audit::finish(Some(self.exit_code), None);
+        exit(self.exit_code)
+    }
+}
+
+impl From<std::io::Error> for Error {
+    fn from(err: std::io::Error) -> Self {
+        Error::new(ErrorCode::Io, format!("IO Error: {err}"))
+    }
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn json() {
+        let error = Error::new(ErrorCode::UnknownFlag, "Unknown flag")
+            .method("net.wifi.connect")
+            .method("ignored")
+            .contract("connect")
+            .token("--x");
+
+        assert_eq!(
+            serde_json::to_string(&error).unwrap(),
+            r#"{"code":"E_UNKNOWN_FLAG","message":"Unknown flag","method":"net.wifi.connect","contract":"connect","token":"--x","exit_code":2}"#
+        );
+
+        assert_eq!(
+            serde_json::to_string(&Error::new(ErrorCode::Io, "IO Error")).unwrap(),
+            r#"{"code":"E_IO","message":"IO Error","exit_code":1}"#
+        );
+    }
+}
@@ -0,0 +1,382 @@
+use crate::contract::{ArgKind, Contract, Flag, GroupKind, Stdin};
+use crate::stream::Format;
+use std::collections::BTreeMap;
+
+/// xorshift64*, small and stable across builds so that a seed always replays the same call.
+pub struct Rng(u64);
+
+impl Rng {
+    pub fn new(seed: u64) -> Rng {
+        // splitmix64, so that consecutive seeds start far apart. A zero state would only
+        // ever produce zeros.
+        let mut z = seed.wrapping_add(0x9e3779b97f4a7c15);
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+        Rng((z ^ (z >> 31)).max(1))
+    }
+
+    pub fn next(&mut self) -> u64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        self.0.wrapping_mul(0x2545f4914f6cdd1d)
+    }
+
+    /// A number in `0..bound`, `0` if `bound` is `0`.
+    pub fn below(&mut self, bound: usize) -> usize {
+        match bound {
+            0 => 0,
+            _ => (self.next() % bound as u64) as usize,
+        }
+    }
+
+    pub fn chance(&mut self) -> bool {
+        self.next() & 1 == 1
+    }
+
+    fn pick<'a, T>(&mut self, items: &'a [T]) -> &'a T {
+        &items[self.below(items.len())]
+    }
+}
+
+/// A generated call. An invalid call is a valid one broken by `mutation`.
+#[derive(Debug, PartialEq)]
+pub struct Call {
+    pub argv: Vec<String>,
+    pub stdin: Option<String>,
+    pub mutation: Option<&'static str>,
+}
+
+/// Generates a call that matches `contract`, or that breaks it in one way if `valid` is false.
+pub fn generate(contract: &Contract, rng: &mut Rng, valid: bool) -> Call {
+    let mut flags = flags(contract, rng);
+    let mut positional = positional(contract, rng);
+    let stdin = stdin(contract, rng);
+
+    let mutation = match valid {
+        true => None,
+        false => Some(mutate(contract, rng, &mut flags, &mut positional)),
+    };
+
+    let mut argv = Vec::new();
+    for (flag, values) in flags {
+        // A value given to a switch is only a value when it is attached.
+        let attached = declared(contract)
+            .iter()
+            .find(|x| *x == &flag)
+            .is_none_or(|x| !x.required_value());
+
+        for value in values {
+            match value {
+                Some(value) if attached || rng.chance() => argv.push(format!("{flag}={value}")),
+                Some(value) => argv.extend([flag.clone(), value]),
+                None => argv.push(flag.clone()),
+            }
+        }
+    }
+    argv.extend(positional);
+
+    Call {
+        argv,
+        stdin,
+        mutation,
+    }
+}
+
+/// The flags of the contract in a stable order, a seed has to draw the same flags every time.
+fn declared(contract: &Contract) -> Vec<Flag> {
+    let mut flags = contract.flags().into_iter().collect::<Vec<_>>();
+    flags.sort_by(|a, b| a.name().cmp(b.name()));
+    flags
+}
+
+/// Every occurrence of every flag, `None` for a flag without a value.
+type Flags = BTreeMap<String, Vec<Option<String>>>;
+
+fn flags(contract: &Contract, rng: &mut Rng) -> Flags {
+    let declared = declared(contract);
+    let mut flags = Flags::new();
+
+    for flag in &declared {
+        if flag.is_required() || rng.chance() {
+            flags.insert(flag.name().clone(), occurrences(flag, rng));
+        }
+    }
+
+    // Twice, so that a group fixed by a later one is fixed again.
+    for _ in 0..2 {
+        for group in contract.groups() {
+            let present = group
+                .flags()
+                .iter()
+                .filter(|x| flags.contains_key(*x))
+                .cloned()
+                .collect::<Vec<_>>();
+
+            match group.kind() {
+                GroupKind::OneOf | GroupKind::AtMostOneOf => {
+                    let keep = match (group.kind(), present.is_empty()) {
+                        (GroupKind::OneOf, true) => Some(rng.pick(group.flags()).clone()),
+                        (_, true) => None,
+                        (_, false) => Some(rng.pick(&present).clone()),
+                    };
+
+                    for name in group.flags() {
+                        if Some(name) != keep.as_ref() {
+                            flags.remove(name);
+                        }
+                    }
+                    if let Some(name) = keep
+                        && let Some(flag) = declared.iter().find(|x| *x == &name)
+                    {
+                        flags.entry(name).or_insert_with(|| occurrences(flag, rng));
+                    }
+                }
+                GroupKind::Requires => {
+                    if !flags.contains_key(&group.flags()[0]) {
+                        continue;
+                    }
+                    for name in &group.flags()[1..] {
+                        if let Some(flag) = declared.iter().find(|x| *x == name) {
+                            flags.entry(name.clone()).or_insert_with(|| occurrences(flag, rng));
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    flags
+}
+
+fn occurrences(flag: &Flag, rng: &mut Rng) -> Vec<Option<String>> {
+    let count = if flag.is_repeatable() { 1 + rng.below(3) } else { 1 };
+
+    (0..count)
+        .map(|_| flag.required_value().then(|| word(rng)))
+        .collect()
+}
+
+/// Values for the arguments, as many per argument as [`crate::argv`] assigns to it.
+fn positional(contract: &Contract, rng: &mut Rng) -> Vec<String> {
+    let args = contract.args();
+    let total = match contract.max_args() {
+        Some(max) => contract.min_args() + rng.below(max - contract.min_args() + 1),
+        None => contract.min_args() + rng.below(4),
+    };
+
+    let mut rest = total;
+    let mut values = Vec::new();
+    for (i, arg) in args.iter().enumerate() {
+        let reserved: usize = args[i + 1..].iter().map(|x| x.min()).sum();
+        let available = rest.saturating_sub(reserved);
+        let take = arg.max().map_or(available, |x| x.min(available));
+
+        values.extend((0..take).map(|_| value(arg.kind(), rng)));
+        rest -= take;
+    }
+
+    values
+}
+
+fn value(kind: ArgKind, rng: &mut Rng) -> String {
+    let number = rng.below(1000);
+
+    match kind {
+        ArgKind::Int => number.to_string(),
+        ArgKind::Num => format!("{number}.{}", rng.below(100)),
+        ArgKind::Text => word(rng),
+    }
+}
+
+/// A lowercase word, which can never be taken for a flag.
+fn word(rng: &mut Rng) -> String {
+    (0..1 + rng.below(8))
+        .map(|_| (b'a' + rng.below(26) as u8) as char)
+        .collect()
+}
+
+fn stdin(contract: &Contract, rng: &mut Rng) -> Option<String> {
+    if *contract.stdin() == Stdin::None || (*contract.stdin() == Stdin::Optional && rng.chance()) {
+        return None;
+    }
+
+    Some(match contract.stdin_format() {
+        Some(Format::Json) => format!("{{\"{}\": {}}}\n", word(rng), rng.below(100)),
+        Some(Format::Jsonl) => (0..1 + rng.below(3))
+            .map(|_| format!("{{\"{}\": {}}}\n", word(rng), rng.below(100)))
+            .collect(),
+        _ => format!("{}\n", word(rng)),
+    })
+}
+
+/// Breaks a valid call in one of the ways that apply to the contract.
+fn mutate(
+    contract: &Contract,
+    rng: &mut Rng,
+    flags: &mut Flags,
+    positional: &mut Vec<String>,
+) -> &'static str {
+    let declared = declared(contract);
+    let required = declared.iter().filter(|x| x.is_required()).collect::<Vec<_>>();
+    let switches = declared.iter().filter(|x| !x.required_value()).collect::<Vec<_>>();
+    let single = declared
+        .iter()
+        .filter(|x| x.required_value() && !x.is_repeatable())
+        .collect::<Vec<_>>();
+    let typed = contract.args().iter().any(|x| x.kind().is_numeric());
+    let exclusive = contract
+        .groups()
+        .iter()
+        .filter(|x| x.kind() != GroupKind::Requires)
+        .cloned()
+        .collect::<Vec<_>>();
+
+    let mut mutations = vec!["unknown flag"];
+    if contract.min_args() > 0 {
+        mutations.push("missing argument");
+    }
+    if contract.max_args().is_some() {
+        mutations.push("extra argument");
+    }
+    if typed {
+        mutations.push("wrong type");
+    }
+    if !required.is_empty() {
+        mutations.push("missing flag");
+    }
+    if !switches.is_empty() {
+        mutations.push("unexpected value");
+    }
+    if !single.is_empty() {
+        mutations.push("repeated flag");
+    }
+    if !exclusive.is_empty() {
+        mutations.push("exclusive flags");
+    }
+
+    let mutation = *rng.pick(&mutations);
+    match mutation {
+        "missing argument" => {
+            positional.truncate(contract.min_args() - 1);
+        }
+        "extra argument" => {
+            let max = contract.max_args().unwrap_or_default();
+            while positional.len() <= max {
+                positional.push(word(rng));
+            }
+        }
+        "wrong type" => {
+            // Only numeric arguments, filled by the same split as `positional`.
+            let args = contract.args();
+            let mut at = 0;
+            let mut rest = positional.len();
+            let mut slots = Vec::new();
+            for (i, arg) in args.iter().enumerate() {
+                let reserved: usize = args[i + 1..].iter().map(|x| x.min()).sum();
+                let take = arg.max().map_or(rest.saturating_sub(reserved), |x| {
+                    x.min(rest.saturating_sub(reserved))
+                });
+                if arg.kind().is_numeric() {
+                    slots.extend(at..at + take);
+                }
+                at += take;
+                rest -= take;
+            }
+
+            if slots.is_empty() {
+                return mutate_unknown(rng, flags);
+            }
+            let slot = *rng.pick(&slots);
+            positional[slot] = format!("x{}", word(rng));
+        }
+        "missing flag" => {
+            flags.remove(rng.pick(&required).name());
+        }
+        "unexpected value" => {
+            let flag = rng.pick(&switches).name().clone();
+            flags.insert(flag, vec![Some(word(rng))]);
+        }
+        "repeated flag" => {
+            let flag = rng.pick(&single).name().clone();
+            flags.insert(flag, vec![Some(word(rng)), Some(word(rng))]);
+        }
+        "exclusive flags" => {
+            let group = rng.pick(&exclusive);
+            let names = match group.kind() {
+                // Either none or two of them.
+                GroupKind::OneOf if rng.chance() => vec![],
+                _ => group.flags()[..2].to_vec(),
+            };
+
+            for name in group.flags() {
+                flags.remove(name);
+            }
+            for name in names {
+                if let Some(flag) = declared.iter().find(|x| *x == &name) {
+                    flags.insert(name, occurrences(flag, rng));
+                }
+            }
+        }
+        _ => return mutate_unknown(rng, flags),
+    }
+
+    mutation
+}
+
+fn mutate_unknown(rng: &mut Rng, flags: &mut Flags) -> &'static str {
+    flags.insert(format!("--undeclared-{}", word(rng)), vec![None]);
+    "unknown flag"
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::argv;
+
+    #[test]
+    fn replay() {
+        let contract = "connect: ssid! --band=?".parse::<Contract>().unwrap();
+        assert_eq!(
+            generate(&contract, &mut Rng::new(42), true),
+            generate(&contract, &mut Rng::new(42), true)
+        );
+        assert_ne!(Rng::new(20).next(), Rng::new(21).next());
+        assert_ne!(Rng::new(0).next(), 0);
+    }
+
+    #[test]
+    fn valid_and_invalid() {
+        let contracts = [
+            "connect: stdin? ssid! password? --band=? --hidden? -v+ --tag=*",
+            "sum: numbers<int>...! --scale=? @stdin_format json",
+            "move: from! to{1,3} count<num>? --force! --dry?",
+            "net: --wifi? --ethernet? --ssid=? --password=? one_of{--wifi,--ethernet} requires{--password,--ssid}",
+            "pick: --a? --b? --c? at_most_one_of{--a,--b,--c}",
+            "status: stdout?",
+        ];
+
+        for contract in contracts {
+            let contract = contract.parse::<Contract>().unwrap();
+
+            for seed in 0..200 {
+                let call = generate(&contract, &mut Rng::new(seed), true);
+                assert!(
+                    argv::parse(&call.argv, &contract).is_ok(),
+                    "{:?} rejected {:?}",
+                    contract.name(),
+                    call.argv
+                );
+
+                let call = generate(&contract, &mut Rng::new(seed), false);
+                assert!(
+                    argv::parse(&call.argv, &contract).is_err(),
+                    "{:?} accepted {:?} ({:?})",
+                    contract.name(),
+                    call.argv,
+                    call.mutation
+                );
+            }
+        }
+    }
+}
@@ -1,323 +1,698 @@
-use crate::contract::{Contract, Flag, Stdin, Stdout};
+use crate::argv;
+use crate::contract::{Contract, Stdin, Stdout};
+use crate::error::{Error, ErrorCode};
+use crate::config::{runtime_dir, state_dir};
+use crate::header;
+use crate::hooks::{self, Hook};
+use crate::index;
+use crate::learn;
+use crate::lock::{self, LockScope};
 use crate::method::Method;
+use crate::mock::{self, Mocks};
+use crate::state;
+use crate::stream::Input;
+use crate::strictness::{self, Strictness};
+use crate::supervisor;
 use std::collections::HashMap;
 use std::fs;
 use std::io::ErrorKind;
-use std::path::PathBuf;
-use std::process::exit;
+use std::path::{Path, PathBuf};
 use std::str::FromStr;
 
 pub trait Handle {
-    fn handle(method: &mut Method);
+    fn handle(method: &mut Method) -> Result<(), Error>;
 }
 
 pub struct MainHandler;
+/// Finds the object and the contract of the method, following interfaces.
+pub struct ResolveHandler;
+/// Resolves the method and checks the argv against its contract, without side effects.
+pub struct ValidationHandler;
 struct ContractFillerHandler;
 struct InterfaceHandler;
+struct VisibilityHandler;
 struct InterfaceMethodPathReplaceHandler;
 struct InterfaceContractValidator;
 struct ContractValidator;
+struct UncontractedHandler;
+struct LearnHandler;
+struct DeprecationHandler;
 struct StdoutHandler;
 struct StdinHandler;
+struct SupervisionHandler;
+struct MockHandler;
+struct LockHandler;
+struct StateHandler;
+struct HookHandler;
+struct InputHandler;
 
 impl Handle for MainHandler {
-    fn handle(method: &mut Method) {
-        ContractFillerHandler::handle(method);
-        InterfaceHandler::handle(method);
-        ContractValidator::handle(method);
-        StdinHandler::handle(method);
-        StdoutHandler::handle(method);
+    fn handle(method: &mut Method) -> Result<(), Error> {
+        ValidationHandler::handle(method)?;
+        UncontractedHandler::handle(method)?;
+        LearnHandler::handle(method)?;
+        DeprecationHandler::handle(method)?;
+        StdinHandler::handle(method)?;
+        StdoutHandler::handle(method)?;
+        SupervisionHandler::handle(method)?;
+        // Stdin is checked before anything acts on the call.
+        InputHandler::handle(method)?;
+        MockHandler::handle(method)?;
+        LockHandler::handle(method)?;
+        StateHandler::handle(method)?;
+        HookHandler::handle(method)
+    }
+}
+
+impl Handle for ResolveHandler {
+    fn handle(method: &mut Method) -> Result<(), Error> {
+        ContractFillerHandler::handle(method)?;
+        InterfaceHandler::handle(method)?;
+        VisibilityHandler::handle(method)
+    }
+}
+
+impl Handle for ValidationHandler {
+    fn handle(method: &mut Method) -> Result<(), Error> {
+        ResolveHandler::handle(method)?;
+        ContractValidator::handle(method)
     }
 }
 
 impl Handle for ContractFillerHandler {
-    fn handle(method: &mut Method) {
-        if !method.object.path.is_dir() {
-            eprintln!("Object must be a directory");
+    fn handle(method: &mut Method) -> Result<(), Error> {
+        // An object that is not synced locally is described by the `.self` of the host.
+        if let Some(remote) = &method.remote
+            && !method.object.path.is_dir()
+        {
+            let path = remote.path(&method.namespace, &method.object.path.join(".self"));
+            method.object.contracts = match remote.read(&path) {
+                Ok(Some(string)) => Some(parse_contracts(&string)?),
+                Ok(None) => None,
+                Err(err) => {
+                    return Err(Error::new(ErrorCode::Remote, err.to_string()).token(remote.host.clone()));
+                }
+            };
+            return Ok(());
+        }
 
-            exit(1);
+        if index::current().is_none_or(|x| x.object(&method.object.path).is_none())
+            && !method.object.path.is_dir()
+        {
+            return Err(
+                Error::new(ErrorCode::ObjectNotFound, "Object must be a directory")
+                    .token(method.object.name.clone()),
+            );
         }
 
-        method.object.contracts = object_contracts(&method.object.path);
+        let contracts = object_contracts(&method.object.path)?;
+        method.object.contracts = with_headers(&method.object.path, contracts, [&method.name])?;
+        Ok(())
     }
 }
 
 impl Handle for InterfaceHandler {
-    fn handle(method: &mut Method) {
+    fn handle(method: &mut Method) -> Result<(), Error> {
         if !method.object.name.starts_with("__") || !method.object.name.ends_with("__") {
-            return;
+            return Ok(());
         }
 
         if method.object.contracts.is_none() {
-            eprintln!("Interface must have a contract");
-            exit(1)
-        }
-
-        match fs::read_dir(&method.object.path) {
-            Ok(files) => {
-                let mut files_count = 0;
-                for file in files {
-                    match file {
-                        Ok(_file) => {
-                            files_count += 1;
-                        }
-                        Err(err) => {
-                            eprintln!("IO Error: {:?} : {err}", &method.object.path);
-                            exit(1)
-                        }
-                    }
-                }
+            return Err(Error::new(
+                ErrorCode::InterfaceContractMissing,
+                "Interface must have a contract",
+            ));
+        }
 
-                if files_count != 2 {
-                    eprintln!(
-                        "Interfaces must only contain two files: a self file with contracts and a link to an object"
-                    );
-                    exit(1)
-                }
-            }
-            Err(err) => {
-                eprintln!("IO Error: {:?} {err}", &method.object.path);
-                exit(1)
-            }
+        let files_count = match index::current()
+            .and_then(|x| x.object(&method.object.path).and_then(|x| x.interface.clone()))
+        {
+            Some(interface) => interface.entries,
+            None => fs::read_dir(&method.object.path)
+                .and_then(|files| files.collect::<Result<Vec<_>, _>>())
+                .map_err(|err| Error::io(&method.object.path, err))?
+                .len(),
+        };
+
+        if files_count != 2 {
+            return Err(Error::new(
+                ErrorCode::InterfaceLayout,
+                "Interfaces must only contain two files: a self file with contracts and a link to an object",
+            ));
         }
 
-        InterfaceMethodPathReplaceHandler::handle(method);
-        InterfaceContractValidator::handle(method);
+        InterfaceMethodPathReplaceHandler::handle(method)?;
+        InterfaceContractValidator::handle(method)
+    }
+}
+
+impl Handle for VisibilityHandler {
+    fn handle(method: &mut Method) -> Result<(), Error> {
+        if !method.is_private() {
+            return Ok(());
+        }
+
+        let object = method.dotted_name.rsplit_once('.').map(|x| x.0);
+        let caller = method.caller.as_deref().and_then(|x| x.rsplit_once('.')).map(|x| x.0);
+        if caller.is_some() && caller == object {
+            return Ok(());
+        }
+
+        Err(Error::new(
+            ErrorCode::PrivateMethod,
+            format!(
+                "The method \"{}\" is private and can only be called by methods of \"{}\"",
+                method.name,
+                object.unwrap_or_default()
+            ),
+        )
+        .token(method.dotted_name.clone()))
     }
 }
 
 impl Handle for InterfaceMethodPathReplaceHandler {
-    fn handle(method: &mut Method) {
-        let object_link = fs::read_dir(&method.object.path)
-            .unwrap()
-            .map(|x| x.unwrap())
-            .filter(|x| x.file_name().ne(".self"))
-            .map(|x| x.path())
-            .collect::<Vec<_>>()
-            .get(0)
-            .map(|x| x.clone())
-            .unwrap();
-
-        let object_path = fs::canonicalize(object_link).unwrap();
+    fn handle(method: &mut Method) -> Result<(), Error> {
+        if let Some(object_path) = index::current()
+            .and_then(|x| x.object(&method.object.path).and_then(|x| x.interface.clone()))
+            .and_then(|x| x.target)
+        {
+            method.path = object_path.join(&method.name);
+            method.object.path = object_path;
+            return Ok(());
+        }
+
+        let dir = &method.object.path;
+        let mut object_link = None;
+        for entry in fs::read_dir(dir).map_err(|err| Error::io(dir, err))? {
+            let entry = entry.map_err(|err| Error::io(dir, err))?;
+            if entry.file_name().ne(".self") {
+                object_link = Some(entry.path());
+                break;
+            }
+        }
+
+        let Some(object_link) = object_link else {
+            return Err(Error::new(
+                ErrorCode::InterfaceLayout,
+                "Interfaces must only contain two files: a self file with contracts and a link to an object",
+            ));
+        };
+        let object_path = fs::canonicalize(&object_link).map_err(|err| Error::io(&object_link, err))?;
         method.path = object_path.join(&method.name);
         method.object.path = object_path;
+        Ok(())
     }
 }
 
 impl Handle for InterfaceContractValidator {
-    fn handle(method: &mut Method) {
+    fn handle(method: &mut Method) -> Result<(), Error> {
         let interface_contracts = method.object.contracts.as_ref().unwrap();
-        let object_contracts = object_contracts(&method.object.path);
+        let object_contracts = with_headers(
+            &method.object.path,
+            object_contracts(&method.object.path)?,
+            interface_contracts.keys(),
+        )?;
         if object_contracts.is_none() {
-            eprintln!(
-                "The object \"{}\" referenced by the interface must contain a contract.",
-                method.object.name
-            );
-            exit(1)
+            return Err(Error::new(
+                ErrorCode::ObjectContractMissing,
+                format!(
+                    "The object \"{}\" referenced by the interface must contain a contract.",
+                    method.object.name
+                ),
+            ));
         }
 
         for (method, contract) in interface_contracts {
             match object_contracts.as_ref().unwrap().get(method) {
-                Some(object_contract) if contract.eq(object_contract) => {}
+                Some(object_contract)
+                    if contract
+                        .min_version()
+                        .is_some_and(|x| object_contract.version() < x) =>
+                {
+                    return Err(Error::new(
+                        ErrorCode::InterfaceVersion,
+                        format!(
+                            "The interface requires version {} of the contract, the object provides version {}",
+                            contract.min_version().unwrap_or_default(),
+                            object_contract.version()
+                        ),
+                    )
+                    .contract(method.clone()));
+                }
+                Some(object_contract) if contract.same_signature(object_contract) => {}
                 _ => {
-                    eprintln!(
-                        "The enumeration of interface contracts must be a subset of the enumeration of contracts of the object referenced by the interface"
-                    );
-                    exit(1)
+                    return Err(Error::new(
+                        ErrorCode::InterfaceMismatch,
+                        "The enumeration of interface contracts must be a subset of the enumeration of contracts of the object referenced by the interface",
+                    )
+                    .contract(method.clone()));
                 }
             }
         }
 
         if !interface_contracts.contains_key(&method.name) {
             if object_contracts.unwrap().contains_key(&method.name) {
-                eprintln!(
-                    "The called method \"{}\" is not specified in the interface contract. However, it is specified in the objects contract: {:?}",
-                    method.name, method.object.path
-                );
-                exit(1)
+                return Err(Error::new(
+                    ErrorCode::MethodNotInInterface,
+                    format!(
+                        "The called method \"{}\" is not specified in the interface contract. However, it is specified in the objects contract: {:?}",
+                        method.name, method.object.path
+                    ),
+                )
+                .token(method.name.clone()));
             }
 
-            eprintln!(
-                "The called method \"{}\" is not specified in the interface contract.",
-                method.name
-            );
-            exit(1)
+            return Err(Error::new(
+                ErrorCode::MethodNotInInterface,
+                format!(
+                    "The called method \"{}\" is not specified in the interface contract.",
+                    method.name
+                ),
+            )
+            .token(method.name.clone()));
         }
 
         method.object.contracts = object_contracts;
+        Ok(())
     }
 }
 
 impl Handle for ContractValidator {
-    fn handle(method: &mut Method) {
-        if let Some(contracts) = &method.object.contracts {
-            if let Some(contract) = contracts.get(&method.name) {
-                let contract_flags: HashMap<String, Flag> = contract
-                    .flags()
-                    .iter()
-                    .cloned()
-                    .map(|x| (x.name().clone(), x))
-                    .collect();
-
-                let mut flags = Vec::new();
-                let mut args_count = 0;
-
-                let mut requires_value: (String, bool) = (String::default(), false);
-                for arg in method.args.iter().cloned() {
-                    if arg.starts_with("-") {
-                        if requires_value.1 {
-                            break
-                        }
-                        let flag_has_value: bool;
-                        let flag_name: String;
-
-                        if let Some(i) = arg.find("=") {
-                            flag_name = arg[..i].to_string();
-                            flag_has_value = true;
-                        } else {
-                            flag_name = arg;
-                            flag_has_value = false;
-                        }
-
-                        match contract_flags.get(&flag_name) {
-                            Some(flag) => {
-                                requires_value =
-                                    (flag_name.clone(), flag.required_value() && !flag_has_value);
-                            }
-                            None => {
-                                eprintln!(
-                                    "A flag was provided that is not in the contract: {flag_name}"
-                                );
-                                exit(1);
-                            }
-                        }
-
-                        flags.push(flag_name);
-                        continue
-                    }
-
-                    if !requires_value.1 {
-                        args_count += 1;
-                    }
-                    requires_value = (String::default(), false);
-                }
+    fn handle(method: &mut Method) -> Result<(), Error> {
+        if let Some(contracts) = &method.object.contracts
+            && let Some(contract) = contracts.get(&method.name)
+        {
+            method.parsed = Some(argv::parse(&method.args, contract)?);
+        }
 
-                if requires_value.1 {
-                    eprintln!(
-                        "The flag \"{}\" must have a value, which is not provided",
-                        requires_value.0
-                    );
-                    exit(1);
-                }
+        Ok(())
+    }
+}
 
-                if contract.required_args().len() > args_count {
-                    eprintln!(
-                        "The arguments provided are fewer than required by the contract. The contract requires {} arguments and {} optional ones.",
-                        contract.required_args().len(),
-                        contract.args().len() - contract.required_args().iter().len()
-                    );
-                    exit(2)
-                }
+impl Handle for UncontractedHandler {
+    fn handle(method: &mut Method) -> Result<(), Error> {
+        if method.contract().is_some() {
+            return Ok(());
+        }
 
-                if contract.args().len() < args_count {
-                    eprintln!(
-                        "Too many arguments. The contract requires {} arguments and {} optional ones.",
-                        contract.required_args().len(),
-                        contract.args().len() - contract.required_args().iter().len()
-                    );
-                    exit(2)
-                }
+        let message = format!("The method \"{}\" has no contract", method.dotted_name);
+        match method.strictness() {
+            Strictness::Strict => {
+                Err(Error::new(ErrorCode::Uncontracted, message).token(method.dotted_name.clone()))
+            }
+            Strictness::Warn => {
+                eprintln!("Warning: {message}");
+                Ok(())
+            }
+            Strictness::Off => Ok(()),
+        }
+    }
+}
 
-                let contract_flags = contract_flags.values().collect::<Vec<_>>();
-                for flag in &contract_flags {
-                    if flag.is_required() && !flags.contains(flag.name()) {
-                        eprintln!("One required flag is missing: {}", flag.name());
-                        exit(2)
-                    }
-                }
+/// Observes the calls of uncontracted methods while `irnix learn` runs.
+impl Handle for LearnHandler {
+    fn handle(method: &mut Method) -> Result<(), Error> {
+        learn::begin(method);
+        Ok(())
+    }
+}
 
-                let contract_flags = contract_flags.iter().map(|x| x.name()).collect::<Vec<_>>();
-                for flag in flags {
-                    if !contract_flags.contains(&&flag) {
-                        eprintln!("The flag {flag} is not mentioned in the contract");
-                        exit(2)
-                    }
-                }
-            }
+impl Handle for DeprecationHandler {
+    fn handle(method: &mut Method) -> Result<(), Error> {
+        let Some(note) = method.contract().and_then(|x| x.deprecated()) else {
+            return Ok(());
+        };
+
+        let message = match note.is_empty() {
+            true => format!("The method \"{}\" is deprecated", method.dotted_name),
+            false => format!("The method \"{}\" is deprecated: {note}", method.dotted_name),
+        };
+
+        if method.strictness() == Strictness::Strict {
+            return Err(Error::new(ErrorCode::Deprecated, message).token(method.dotted_name.clone()));
         }
+
+        eprintln!("Warning: {message}");
+        Ok(())
     }
 }
 
 impl Handle for StdoutHandler {
-    fn handle(method: &mut Method) {
-        if let Some(contracts) = &method.object.contracts {
-            if let Some(contract) = contracts.get(&method.name) {
-                match contract.stdout() {
-                    Stdout::None => {
-                        if atty::isnt(atty::Stream::Stdout) {
-                            eprintln!(
-                                "The contract {} does not imply functionality for stdout, but stdout is used in pipeline.",
-                                contract.name()
-                            );
-                            exit(1);
-                        }
-                    }
-                    _ => return,
+    fn handle(method: &mut Method) -> Result<(), Error> {
+        if let Some(contracts) = &method.object.contracts
+            && let Some(contract) = contracts.get(&method.name)
+            && contract.stdout() == &Stdout::None
+            && method.stdout.is_pipeline()
+        {
+            return Err(Error::new(
+                ErrorCode::StdoutUnexpected,
+                format!(
+                    "The contract {} does not imply functionality for stdout, but stdout is used in pipeline.",
+                    contract.name()
+                ),
+            )
+            .contract(contract.name()));
+        }
+
+        Ok(())
+    }
+}
+
+impl Handle for StdinHandler {
+    fn handle(method: &mut Method) -> Result<(), Error> {
+        if !method.stdin_check {
+            return Ok(());
+        }
+
+        if let Some(contracts) = &method.object.contracts
+            && let Some(contract) = contracts.get(&method.name)
+        {
+            let expected = contract.stdin_stream();
+            let stdin = method.stdin.stream();
+            let provided = match expected {
+                Some(stream) => stdin == stream,
+                None => stdin.is_provided(),
+            };
+
+            match contract.stdin() {
+                Stdin::Required if !provided => {
+                    let message = match expected {
+                        Some(stream) => format!(
+                            "The contract \"{}\" requires stdin from {}, but stdin is {}",
+                            contract.name(),
+                            stream.as_str(),
+                            stdin.as_str()
+                        ),
+                        None => format!(
+                            "The contract \"{}\" requires stdin, which is not provided",
+                            contract.name()
+                        ),
+                    };
+
+                    return Err(Error::new(ErrorCode::StdinRequired, message)
+                        .contract(contract.name()));
                 }
+                Stdin::Optional if !provided && stdin.is_provided() => {
+                    return Err(Error::new(
+                        ErrorCode::StdinUnexpected,
+                        format!(
+                            "The contract \"{}\" accepts stdin only from {}, but stdin is {}",
+                            contract.name(),
+                            expected.map(|x| x.as_str()).unwrap_or_default(),
+                            stdin.as_str()
+                        ),
+                    )
+                    .contract(contract.name()));
+                }
+                Stdin::None if stdin.is_provided() => {
+                    return Err(Error::new(
+                        ErrorCode::StdinUnexpected,
+                        format!(
+                            "The contract \"{}\" does not imply functionality for stdin, but stdin was passed.",
+                            contract.name()
+                        ),
+                    )
+                    .contract(contract.name()));
+                }
+                _ => {}
             }
         }
+
+        Ok(())
     }
 }
 
-impl Handle for StdinHandler {
-    fn handle(method: &mut Method) {
-        if let Some(contracts) = &method.object.contracts {
-            if let Some(contract) = contracts.get(&method.name) {
-                match contract.stdin() {
-                    Stdin::Required => {
-                        if method.stdin.is_none() {
-                            eprintln!(
-                                "The contract \"{}\" requires stdin, which is not provided",
-                                contract.name()
-                            );
-                            exit(2);
-                        }
-                    }
-                    Stdin::None => {
-                        if method.stdin.is_some() {
-                            eprintln!(
-                                "The contract \"{}\" does not imply functionality for stdin, but stdin was passed.",
-                                contract.name()
-                            );
-                            exit(2);
-                        }
-                    }
-                    _ => return,
+impl Handle for SupervisionHandler {
+    fn handle(method: &mut Method) -> Result<(), Error> {
+        if let Some(contracts) = &method.object.contracts
+            && let Some(contract) = contracts.get(&method.name)
+            && let Some(declared) = contract.supervision()
+        {
+            method.supervision = Some(method.supervision.take().unwrap_or_default().or(declared));
+        }
+
+        Ok(())
+    }
+}
+
+/// Replaces the method with its mock under `irnix test --mock`, once the call passed the
+/// contract of the method. A mock that declares a contract, in a `#>>>` header or in the
+/// `.self` next to it, must declare the same signature.
+impl Handle for MockHandler {
+    fn handle(method: &mut Method) -> Result<(), Error> {
+        let Some(file) = method.mocks.clone() else {
+            return Ok(());
+        };
+        let mocks = Mocks::load(&file).map_err(|err| Error::io(&file, err))?;
+        let Some(script) = mocks.find(&method.dotted_name) else {
+            return Ok(());
+        };
+
+        if let Some(contract) = method.contract() {
+            let declared = match header::contract(&script, &method.name)? {
+                Some(declared) => Some(declared),
+                None => script
+                    .parent()
+                    .map(|x| object_contracts(&x.to_path_buf()))
+                    .transpose()?
+                    .flatten()
+                    .and_then(|mut x| x.remove(&method.name)),
+            };
+
+            if declared.is_some_and(|x| !x.same_signature(contract)) {
+                return Err(Error::new(
+                    ErrorCode::MockMismatch,
+                    format!("The mock {script:?} does not match the contract \"{}\"", contract.name()),
+                )
+                .contract(contract.name()));
+            }
+        }
+
+        let stdin = match method.stdin.stream().is_provided() {
+            true => {
+                let buffer = method.stdin.read().map_err(|err| {
+                    Error::new(ErrorCode::Io, format!("Failed to read stdin: {err}"))
+                })?;
+                method.stdin = Input::Buffer(buffer.clone());
+                // A read stdin only reaches a supervised child.
+                method.supervision.get_or_insert_default();
+                Some(String::from_utf8_lossy(&buffer).to_string())
+            }
+            false => None,
+        };
+
+        let call = mock::Call {
+            method: method.dotted_name.clone(),
+            args: method.args.clone(),
+            stdin,
+        };
+        mock::record(&call, &mock::log(&file))
+            .map_err(|err| Error::new(ErrorCode::Io, format!("Failed to record the mocked call: {err}")))?;
+
+        method.path = script;
+        method.remote = None;
+        Ok(())
+    }
+}
+
+/// Takes the `@lock` of the contract. Hooks run under the lock as part of the call.
+impl Handle for LockHandler {
+    fn handle(method: &mut Method) -> Result<(), Error> {
+        let Some(contract) = method.contract() else {
+            return Ok(());
+        };
+        let Some(scope) = contract.lock() else {
+            return Ok(());
+        };
+
+        // Instances of an object have separate state, so they are locked separately.
+        let key = match scope {
+            LockScope::Method => format!("{}.{}", method.object_key(), method.name),
+            LockScope::Object => method.object_key(),
+        };
+        let wait = method.lock_wait.or(contract.lock_wait()).unwrap_or_default();
+        let path = runtime_dir()?.join(format!("{key}.lock"));
+
+        // The lock stays with the method, a nested call under it would wait for itself.
+        if method.locks.contains(&path) && lock::held_by_caller(&path) {
+            return Ok(());
+        }
+
+        match lock::acquire(&path, wait, &method.dotted_name) {
+            Ok(Some(file)) => {
+                method.lock = Some(file);
+                if !method.locks.contains(&path) {
+                    method.locks.push(path);
                 }
+                Ok(())
             }
+            Ok(None) => Err(Error::new(
+                ErrorCode::Locked,
+                format!(
+                    "The lock \"{key}\" is held by another call: {}",
+                    fs::read_to_string(&path).unwrap_or_default().trim()
+                ),
+            )
+            .token(key)),
+            Err(err) => Err(Error::io(&path, err)),
         }
     }
 }
 
-fn object_contracts(object_path: &PathBuf) -> Option<HashMap<String, Contract>> {
+/// Creates the state directory of the object and runs its `.init` the first time.
+/// Methods on a remote host keep their state there.
+impl Handle for StateHandler {
+    fn handle(method: &mut Method) -> Result<(), Error> {
+        if method.remote.is_some() {
+            return Ok(());
+        }
+
+        // Only objects that use the state get the directory, so that the other methods
+        // still run when it can not be created.
+        let init = method.object.path.join(".init");
+        let used = init.is_file()
+            || method.instance.is_some()
+            || method.contract().is_some_and(|x| x.state());
+        if !used {
+            return Ok(());
+        }
+
+        let root = state_dir();
+        let path = state::path(&root, &method.object_key());
+        method.state = Some(path.clone());
+
+        if !init.is_file() {
+            state::create(&path).map_err(|err| Error::io(&path, err))?;
+            return Ok(());
+        }
+
+        // Held until the state is initialised, concurrent first calls wait for it.
+        let _lock = state::lock(&root, &method.object_key()).map_err(|err| Error::io(&root, err))?;
+        if !state::create(&path).map_err(|err| Error::io(&path, err))? {
+            return Ok(());
+        }
+
+        let message = match state::init(&init, method) {
+            Ok(status) if status.success() => return Ok(()),
+            Ok(status) => format!(
+                "The initializer of \"{}\" failed with exit code {}",
+                method.object_key(),
+                supervisor::exit_code(status)
+            ),
+            Err(err) => format!("The initializer {:?} failed to start: {err}", init),
+        };
+
+        // The next call initializes the state again.
+        let _ = fs::remove_dir_all(&path);
+        Err(Error::new(ErrorCode::Init, message).token(method.object_key()))
+    }
+}
+
+/// Runs the `.before` hooks and keeps the `.after` hooks for the end of the call.
+/// Since irnix has to outlive the method to run them, after hooks imply supervision.
+impl Handle for HookHandler {
+    fn handle(method: &mut Method) -> Result<(), Error> {
+        for hook in hooks::find(method, Hook::Before) {
+            let message = match hooks::run(&hook, method, None) {
+                Ok(status) if status.success() => continue,
+                Ok(status) => format!(
+                    "The hook {:?} refused the call with exit code {}",
+                    hook,
+                    supervisor::exit_code(status)
+                ),
+                Err(err) => format!("The hook {:?} failed to start: {err}", hook),
+            };
+
+            return Err(Error::new(ErrorCode::Hook, message).token(hook.to_string_lossy()));
+        }
+
+        method.after_hooks = hooks::find(method, Hook::After);
+        if !method.after_hooks.is_empty() {
+            method.supervision.get_or_insert_default();
+        }
+
+        Ok(())
+    }
+}
+
+/// Buffers stdin of a supervised method to check its declared format, to record it
+/// or to key the cache of a `@pure` method.
+impl Handle for InputHandler {
+    fn handle(method: &mut Method) -> Result<(), Error> {
+        let contract = method.contract();
+        let format = contract
+            .and_then(|x| x.stdin_format())
+            .filter(|_| method.stdin_check);
+        let pure = contract.is_some_and(|x| x.pure());
+        let record = method.supervision.as_ref().and_then(|x| x.record.clone());
+
+        if (format.is_none() && record.is_none() && !pure) || !method.stdin.stream().is_provided() {
+            return Ok(());
+        }
+
+        let buffer = method
+            .stdin
+            .read()
+            .map_err(|err| Error::new(ErrorCode::Io, format!("Failed to read stdin: {err}")))?;
+
+        if let Some(format) = format {
+            format.validate(&buffer).map_err(|err| {
+                Error::new(ErrorCode::StdinFormat, err).contract(method.name.clone())
+            })?;
+        }
+
+        if let Some(record) = record {
+            fs::write(&record, &buffer).map_err(|err| Error::io(&record, err))?;
+        }
+
+        method.stdin = Input::Buffer(buffer);
+        Ok(())
+    }
+}
+
+pub(crate) fn object_contracts(object_path: &PathBuf) -> Result<Option<HashMap<String, Contract>>, Error> {
+    if let Some(index) = index::current()
+        && let Some(object) = index.object(object_path)
+    {
+        return object.contracts.as_deref().map(parse_contracts).transpose();
+    }
+
     match fs::read_to_string(object_path.join(".self")) {
-        Ok(string) => Some(
-            string
-                .lines()
-                .map(|x| match Contract::from_str(x) {
-                    Ok(contract) => contract,
-                    Err(_err) => {
-                        eprintln!("Error while parsing contract: {x}");
-                        exit(2);
-                    }
-                })
-                .map(|x| (x.name().clone(), x))
-                .collect::<HashMap<String, Contract>>(),
-        ),
-        Err(err) if err.kind() == ErrorKind::NotFound => None,
-        Err(err) => {
-            eprintln!("IO Error: {:?} : {err}", object_path);
-            exit(1);
+        Ok(string) => parse_contracts(&string).map(Some),
+        Err(err) if err.kind() == ErrorKind::NotFound => Ok(None),
+        Err(err) => Err(Error::io(object_path, err)),
+    }
+}
+
+/// Adds the contracts in the headers of the `methods` files that `.self` does not declare.
+/// A contract in `.self` always wins over the one in the file.
+pub(crate) fn with_headers<'a>(
+    object_path: &Path,
+    contracts: Option<HashMap<String, Contract>>,
+    methods: impl IntoIterator<Item = &'a String>,
+) -> Result<Option<HashMap<String, Contract>>, Error> {
+    let mut contracts = contracts;
+
+    for name in methods {
+        if contracts.as_ref().is_some_and(|x| x.contains_key(name)) {
+            continue;
+        }
+
+        if let Some(contract) = header::contract(&object_path.join(name), name)?
+            && contract.name() == name
+        {
+            contracts.get_or_insert_default().insert(name.clone(), contract);
         }
     }
+
+    Ok(contracts)
+}
+
+pub(crate) fn parse_contracts(string: &str) -> Result<HashMap<String, Contract>, Error> {
+    string
+        .lines()
+        .filter(|x| !strictness::is_directive(x))
+        .map(Contract::from_str)
+        .map(|x| x.map(|x| (x.name().clone(), x)))
+        .collect()
 }
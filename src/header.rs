@@ -0,0 +1,76 @@
+use crate::contract::Contract;
+use crate::contract_tokens::ContractTokens;
+use crate::error::Error;
+use logos::Logos;
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+use std::str::FromStr;
+
+/// How much of a method file is searched for a contract.
+const HEAD_SIZE: u64 = 4096;
+
+/// The `#>>>` contract in the comment block at the head of a method file, such as
+///
+/// ```sh
+/// #!/bin/sh
+/// #>>> stdin? -> ssid! -> stdout?
+/// ```
+///
+/// A contract without a name is named after the method. A file that cannot be read
+/// has no header, running it reports the actual problem.
+pub fn contract(path: &Path, name: &str) -> Result<Option<Contract>, Error> {
+    let mut head = Vec::new();
+    let read = File::open(path).and_then(|x| x.take(HEAD_SIZE).read_to_end(&mut head));
+    if read.is_err() {
+        return Ok(None);
+    }
+
+    line(&String::from_utf8_lossy(&head), name)
+        .map(|x| Contract::from_str(&x))
+        .transpose()
+}
+
+/// The first `#>>>` line before the code starts, with the name of the method added if it has none.
+fn line(text: &str, name: &str) -> Option<String> {
+    for line in text.lines().map(str::trim) {
+        if let Some(body) = line.strip_prefix("#>>>") {
+            let named = ContractTokens::lexer(body).any(|x| x == Ok(ContractTokens::Name));
+
+            return Some(match named {
+                true => line.to_string(),
+                false => format!("#>>> {name}:{body}"),
+            });
+        }
+
+        if !line.is_empty() && !line.starts_with('#') {
+            return None;
+        }
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn header() {
+        let script = "#!/bin/sh\n\n# Connects to a network.\n#>>> stdin? -> ssid! -> stdout?\necho\n";
+        assert_eq!(
+            line(script, "connect"),
+            Some("#>>> connect: stdin? -> ssid! -> stdout?".into())
+        );
+        assert_eq!(
+            line("#!/bin/sh\n#>>> join: ssid!\n", "connect"),
+            Some("#>>> join: ssid!".into())
+        );
+        assert_eq!(line("#!/bin/sh\necho\n#>>> ssid!\n", "connect"), None);
+        assert_eq!(line("\u{7f}ELF", "connect"), None);
+
+        let contract = Contract::from_str(&line(script, "connect").unwrap()).unwrap();
+        assert_eq!(contract.name(), "connect");
+        assert_eq!(contract.min_args(), 1);
+    }
+}
@@ -0,0 +1,89 @@
+use crate::method::Method;
+use std::io;
+use std::os::fd::AsFd;
+use std::path::PathBuf;
+use std::process::{ExitStatus, Stdio};
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Hook {
+    Before,
+    After,
+}
+
+impl Hook {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Hook::Before => "before",
+            Hook::After => "after",
+        }
+    }
+}
+
+/// The `.before` or `.after` executables of the object of `method` and of every object
+/// above it. Before hooks run outermost first, after hooks innermost first.
+pub fn find(method: &Method, hook: Hook) -> Vec<PathBuf> {
+    let entities = method.dotted_name.split(".").collect::<Vec<&str>>();
+
+    let mut hooks = (1..entities.len())
+        .map(|x| {
+            method
+                .namespace
+                .join(entities[..x].join("/"))
+                .join(format!(".{}", hook.as_str()))
+        })
+        .filter(|x| x.is_file())
+        .collect::<Vec<_>>();
+
+    if hook == Hook::After {
+        hooks.reverse();
+    }
+
+    hooks
+}
+
+/// Runs `path` with the environment of the call, `IRNIX_HOOK` and, after the call,
+/// `IRNIX_EXIT_CODE`. The output of a hook goes to stderr to keep stdout to the method.
+pub fn run(path: &PathBuf, method: &Method, exit_code: Option<i32>) -> io::Result<ExitStatus> {
+    let hook = if exit_code.is_some() { Hook::After } else { Hook::Before };
+
+    let mut command = method.command(path);
+    command
+        .stdin(Stdio::null())
+        .stdout(Stdio::from(io::stderr().as_fd().try_clone_to_owned()?))
+        .env("IRNIX_HOOK", hook.as_str());
+
+    if let Some(exit_code) = exit_code {
+        command.env("IRNIX_EXIT_CODE", exit_code.to_string());
+    }
+
+    command.status()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::temp_dir::TempDir;
+    use std::fs;
+
+    #[test]
+    fn order() {
+        let namespace = TempDir::new("hooks");
+        fs::create_dir_all(namespace.join("net/wifi")).unwrap();
+        for hook in ["net/.before", "net/wifi/.before", "net/.after", "net/wifi/.after"] {
+            fs::write(namespace.join(hook), "").unwrap();
+        }
+
+        let method = Method::new("net.wifi.status".into(), vec![], namespace.to_path_buf()).unwrap();
+        assert_eq!(
+            find(&method, Hook::Before),
+            vec![namespace.join("net/.before"), namespace.join("net/wifi/.before")]
+        );
+        assert_eq!(
+            find(&method, Hook::After),
+            vec![namespace.join("net/wifi/.after"), namespace.join("net/.after")]
+        );
+
+        let method = Method::new("net.up".into(), vec![], namespace.to_path_buf()).unwrap();
+        assert_eq!(find(&method, Hook::Before), vec![namespace.join("net/.before")]);
+    }
+}
@@ -0,0 +1,300 @@
+use crate::cache;
+use crate::commands::methods;
+use crate::error::Error;
+use crate::header;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashSet};
+use std::fs;
+use std::io::{self, ErrorKind};
+use std::os::unix::fs::MetadataExt;
+use std::path::{self, Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+/// Bumped whenever the layout of the index changes, older indexes are rebuilt.
+const VERSION: u32 = 3;
+
+/// The index used by the running process, if it was not disabled with `--no-cache`.
+static CURRENT: Mutex<Option<Arc<Index>>> = Mutex::new(None);
+
+/// What resolution and listing need from a namespace, kept under the cache dir so that
+/// they do not walk it again on every call.
+///
+/// The index is fresh while the modification times of every directory, `.self` file and
+/// method file with a `#>>>` header it was built from are unchanged. Adding, removing or
+/// renaming an entry changes the modification time of its directory.
+#[derive(Serialize, Deserialize, Default, Debug)]
+pub struct Index {
+    version: u32,
+    /// `(seconds, nanoseconds)` by path.
+    stamps: BTreeMap<PathBuf, (i64, i64)>,
+    /// Dotted names, as listed by `irnix methods`.
+    methods: Vec<String>,
+    /// Every object directory by its absolute path.
+    objects: BTreeMap<PathBuf, Object>,
+}
+
+#[derive(Serialize, Deserialize, Default, Debug, Clone)]
+pub struct Object {
+    /// Contents of `.self`.
+    pub contracts: Option<String>,
+    pub interface: Option<Interface>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Interface {
+    /// Number of entries of the interface directory.
+    pub entries: usize,
+    /// Canonical path of the object the interface links to.
+    pub target: Option<PathBuf>,
+}
+
+impl Index {
+    /// Walks `namespace` and the objects linked from its interfaces.
+    pub fn build(namespace: &Path) -> Result<Index, Error> {
+        let mut index = Index {
+            version: VERSION,
+            methods: methods::collect(namespace)?,
+            ..Default::default()
+        };
+
+        let mut ancestors = HashSet::new();
+        index
+            .walk(&absolute(namespace), &mut ancestors)
+            .map_err(|err| Error::io(namespace, err))?;
+
+        Ok(index)
+    }
+
+    fn walk(&mut self, dir: &Path, ancestors: &mut HashSet<PathBuf>) -> io::Result<()> {
+        // Only links back to an ancestor are skipped, other aliases of an object are kept.
+        let canonical = fs::canonicalize(dir)?;
+        if !ancestors.insert(canonical.clone()) {
+            return Ok(());
+        }
+
+        self.stamp(dir)?;
+        let mut object = Object::default();
+
+        let contracts = dir.join(".self");
+        match fs::read_to_string(&contracts) {
+            Ok(string) => {
+                self.stamp(&contracts)?;
+                object.contracts = Some(string);
+            }
+            Err(err) if err.kind() == ErrorKind::NotFound => {}
+            Err(err) => return Err(err),
+        }
+
+        let mut entries = 0;
+        let mut children = Vec::new();
+        for entry in fs::read_dir(dir)? {
+            let entry = entry?;
+            entries += 1;
+
+            let name = entry.file_name();
+            let Some(name) = name.to_str().filter(|x| !x.contains('.')) else {
+                continue;
+            };
+            if entry.path().is_dir() {
+                children.push(entry.path());
+            } else if !matches!(header::contract(&entry.path(), name), Ok(None)) {
+                // Editing a header changes the contract without touching the directory.
+                self.stamp(&entry.path())?;
+            }
+        }
+
+        let name = dir.file_name().and_then(|x| x.to_str()).unwrap_or_default();
+        if name.starts_with("__") && name.ends_with("__") {
+            let target = children.first().and_then(|x| fs::canonicalize(x).ok());
+            object.interface = Some(Interface {
+                entries,
+                target: target.clone(),
+            });
+            children = target.into_iter().collect();
+        }
+
+        self.objects.insert(dir.to_path_buf(), object);
+        for child in children {
+            self.walk(&child, ancestors)?;
+        }
+
+        ancestors.remove(&canonical);
+        Ok(())
+    }
+
+    fn stamp(&mut self, path: &Path) -> io::Result<()> {
+        let metadata = fs::metadata(path)?;
+        self.stamps
+            .insert(path.to_path_buf(), (metadata.mtime(), metadata.mtime_nsec()));
+        Ok(())
+    }
+
+    fn is_fresh(&self) -> bool {
+        self.version == VERSION && self.stamps.iter().all(unchanged)
+    }
+
+    pub fn methods(&self) -> &Vec<String> {
+        &self.methods
+    }
+
+    /// The object at `path`, `None` if it was not indexed or changed since. Only the stamps
+    /// of the object itself are checked, so that a call does not check the whole namespace.
+    pub fn object(&self, path: &Path) -> Option<&Object> {
+        let path = absolute(path);
+        let object = self.objects.get(&path)?;
+
+        self.stamps
+            .iter()
+            .filter(|(x, _)| *x == &path || x.parent() == Some(&path))
+            .all(unchanged)
+            .then_some(object)
+    }
+}
+
+fn unchanged((path, stamp): (&PathBuf, &(i64, i64))) -> bool {
+    fs::metadata(path).is_ok_and(|x| (x.mtime(), x.mtime_nsec()) == *stamp)
+}
+
+/// The index of `namespace`, rebuilt and saved if it is missing or stale. Used to list the
+/// methods, which needs the whole namespace to be fresh.
+pub fn load(namespace: &Path) -> Result<Index, Error> {
+    let file = file(namespace);
+
+    if let Some(index) = read(&file).filter(Index::is_fresh) {
+        return Ok(index);
+    }
+
+    let index = Index::build(namespace)?;
+    // An index that cannot be saved, for example in a read only home, is still used by this call.
+    let _ = save(&index, &file);
+    Ok(index)
+}
+
+/// The index of `namespace` for resolving a single call. Objects that changed since it was
+/// built are resolved directly, see [`Index::object`].
+///
+/// A missing index is built and saved. If it cannot be saved, `None` is returned and the
+/// call is resolved directly, instead of walking the namespace on every call.
+pub fn open(namespace: &Path) -> Option<Index> {
+    let file = file(namespace);
+    if let Some(index) = read(&file).filter(|x| x.version == VERSION) {
+        return Some(index);
+    }
+
+    let index = Index::build(namespace).ok()?;
+    save(&index, &file).ok()?;
+    Some(index)
+}
+
+fn read(file: &Path) -> Option<Index> {
+    serde_json::from_slice(&fs::read(file).ok()?).ok()
+}
+
+/// Rebuilds the index of `namespace` after a change reported by inotify.
+pub fn refresh(namespace: &Path) -> Result<Index, Error> {
+    let index = Index::build(namespace)?;
+    let _ = save(&index, &file(namespace));
+    Ok(index)
+}
+
+fn save(index: &Index, file: &Path) -> io::Result<()> {
+    if let Some(dir) = file.parent() {
+        fs::create_dir_all(dir)?;
+    }
+
+    let temporary = file.with_extension(format!("json.{}.tmp", std::process::id()));
+    fs::write(&temporary, serde_json::to_vec(index)?)?;
+    fs::rename(&temporary, file)
+}
+
+fn file(namespace: &Path) -> PathBuf {
+    crate::config::cache_dir().join("index").join(format!(
+        "{}.json",
+        cache::hash(absolute(namespace).as_os_str().as_encoded_bytes())
+    ))
+}
+
+fn absolute(path: &Path) -> PathBuf {
+    path::absolute(path).unwrap_or(path.to_path_buf())
+}
+
+/// Makes `index` the one consulted by resolution in this process.
+pub fn set(index: Option<Index>) {
+    *CURRENT.lock().unwrap() = index.map(Arc::new);
+}
+
+pub fn current() -> Option<Arc<Index>> {
+    CURRENT.lock().unwrap().clone()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::temp_dir::TempDir;
+    use std::os::unix::fs::symlink;
+    use std::thread;
+    use std::time::Duration;
+
+    #[test]
+    fn build_and_invalidate() {
+        let root = TempDir::new("index");
+        let namespace = root.join("ns");
+        fs::create_dir_all(namespace.join("net/wifi")).unwrap();
+        fs::create_dir_all(root.join("impl")).unwrap();
+        fs::write(namespace.join("net/wifi/status"), "").unwrap();
+        fs::write(namespace.join("net/wifi/.self"), "status: stdout?\n").unwrap();
+        fs::write(root.join("impl/status"), "").unwrap();
+        fs::write(root.join("impl/.self"), "status: stdout?\n").unwrap();
+        fs::create_dir_all(namespace.join("__wifi__")).unwrap();
+        fs::write(namespace.join("__wifi__/.self"), "status: stdout?\n").unwrap();
+        symlink(root.join("impl"), namespace.join("__wifi__/impl")).unwrap();
+        // A link back to an ancestor must not be followed forever.
+        symlink(namespace.join("net"), namespace.join("net/wifi/up")).unwrap();
+
+        let index = Index::build(&namespace).unwrap();
+        assert!(index.methods().contains(&"net.wifi.status".to_string()));
+        assert!(index.methods().contains(&"__wifi__.status".to_string()));
+        assert_eq!(
+            index.object(&namespace.join("net/wifi")).unwrap().contracts.as_deref(),
+            Some("status: stdout?\n")
+        );
+        assert!(index.object(&namespace.join("net")).unwrap().contracts.is_none());
+
+        let interface = index.object(&namespace.join("__wifi__")).unwrap();
+        let interface = interface.interface.as_ref().unwrap();
+        assert_eq!(interface.entries, 2);
+        assert_eq!(interface.target, Some(fs::canonicalize(root.join("impl")).unwrap()));
+        assert!(index.object(&fs::canonicalize(root.join("impl")).unwrap()).is_some());
+        assert!(index.is_fresh());
+
+        // Some filesystems only keep the modification time in whole seconds.
+        thread::sleep(Duration::from_millis(1100));
+        fs::write(root.join("impl/.self"), "status: stdout!\n").unwrap();
+        assert!(!index.is_fresh());
+
+        let index = Index::build(&namespace).unwrap();
+        fs::create_dir_all(namespace.join("sys")).unwrap();
+        assert!(!index.is_fresh());
+    }
+
+    #[test]
+    fn stale_objects() {
+        let namespace = TempDir::new("index-stale");
+        fs::create_dir_all(namespace.join("net/wifi")).unwrap();
+        fs::create_dir_all(namespace.join("sys")).unwrap();
+        fs::write(namespace.join("net/wifi/status"), "#!/bin/sh\n#>>> stdout?\n").unwrap();
+        fs::write(namespace.join("net/wifi/scan"), "#!/bin/sh\n").unwrap();
+
+        let index = Index::build(&namespace).unwrap();
+        assert!(index.methods().contains(&"net.wifi.status".to_string()));
+        assert!(index.stamps.contains_key(&namespace.join("net/wifi/status")));
+        assert!(!index.stamps.contains_key(&namespace.join("net/wifi/scan")));
+
+        thread::sleep(Duration::from_millis(1100));
+        fs::write(namespace.join("net/wifi/status"), "#!/bin/sh\n#>>> private stdout?\n").unwrap();
+        assert!(!index.is_fresh());
+        assert!(index.object(&namespace.join("net/wifi")).is_none());
+        // Only the changed object is resolved again.
+        assert!(index.object(&namespace.join("sys")).is_some());
+    }
+}
@@ -0,0 +1,401 @@
+use regex::Regex;
+use std::collections::{BTreeMap, BTreeSet};
+use std::sync::LazyLock;
+
+static COMMENT: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"(^|\s)#.*$").unwrap());
+static SUBSTITUTION: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"\$\([^()]*\)").unwrap());
+static POSITIONAL: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"\$(\d)|\$\{(\d+)([^}]*)\}").unwrap());
+static ALL: LazyLock<Regex> = LazyLock::new(|| Regex::new(r#"\$[@*]|\$\{[@*]\}"#).unwrap());
+static ASSIGNMENT: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r#"^(?:local\s+|readonly\s+)?([A-Za-z_]\w*)=["']?\$\{?(\d)\b"#).unwrap()
+});
+static TESTED: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r#"-[zn]\s+"?\$\{?(\d)\b"#).unwrap());
+static LOOP: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r#"^for\s+([A-Za-z_]\w*)(\s*;|\s+in\s+"?\$(@|\{@\})"?|\s*$)"#).unwrap()
+});
+static CASE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r#"^case\s+"?\$\{?(1|[A-Za-z_]\w*)\}?"?\s+in\b"#).unwrap());
+static BRANCH: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"^\(?\s*([^()]+?)\s*\)\s*(.*)$").unwrap());
+static GETOPTS: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r#"getopts\s+["']?:?([A-Za-z0-9:]+)["']?"#).unwrap());
+static FLAG: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"^--?[a-zA-Z]\w*$").unwrap());
+static VALUE: LazyLock<Regex> = LazyLock::new(|| Regex::new(r#"\$\{?2\b|\$\{1#"#).unwrap());
+static READ: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"(^|[;&|]\s*|\bwhile\s+)read\b").unwrap());
+static CAT: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"\bcat(\s+-)?\s*($|[|;)&]|/dev/stdin)|/dev/stdin").unwrap()
+});
+static OUTPUT: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"(^|[;&|]\s*)(echo|printf|cat|jq|irnix|ls|grep|sed|awk|sort|head|tail|tr|cut|column|date|curl)\b")
+        .unwrap()
+});
+static EXIT: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"\bexit\s+(\d+)\b").unwrap());
+
+/// Words the contract grammar reads as something other than an argument.
+const RESERVED: [&str; 3] = ["stdin", "stdout", "private"];
+
+/// What a static scan of a shell script suggests about the way it is called. It only
+/// reads the text, so it is a draft to review rather than a contract to trust.
+#[derive(Debug, Default, PartialEq)]
+pub struct Inference {
+    args: Vec<Arg>,
+    /// Whether `"$@"` passes on more arguments after the numbered ones.
+    rest: bool,
+    /// Flags by name, `true` for a flag with a value.
+    flags: BTreeMap<String, bool>,
+    stdin: bool,
+    stdout: bool,
+    exit_codes: BTreeSet<u32>,
+    /// What the script parses but the grammar cannot declare.
+    pub skipped: Vec<String>,
+}
+
+#[derive(Debug, Default, PartialEq, Clone)]
+struct Arg {
+    name: Option<String>,
+    optional: bool,
+    int: bool,
+}
+
+pub fn scan(script: &str) -> Inference {
+    let lines = script
+        .lines()
+        .map(|x| COMMENT.replace(x, "").trim().to_string())
+        .collect::<Vec<_>>();
+
+    let mut inference = Inference::default();
+    let parsing = flag_parsing(&lines, &mut inference);
+
+    for (number, line) in lines.iter().enumerate() {
+        if parsing.contains(&number) {
+            continue;
+        }
+
+        for captures in POSITIONAL.captures_iter(line) {
+            let (index, modifier) = match (captures.get(1), captures.get(2)) {
+                (Some(index), _) => (index.as_str(), ""),
+                (None, Some(index)) => (index.as_str(), captures.get(3).map_or("", |x| x.as_str())),
+                _ => continue,
+            };
+            let Some(index) = index.parse::<usize>().ok().filter(|x| *x > 0) else {
+                continue;
+            };
+
+            let arg = inference.arg(index);
+            if ["-", ":-", "=", ":="].iter().any(|x| modifier.starts_with(x)) {
+                arg.optional = true;
+            }
+        }
+
+        if let Some(captures) = ASSIGNMENT.captures(line)
+            && let Ok(index @ 1..) = captures[2].parse::<usize>()
+        {
+            let arg = inference.arg(index);
+            arg.name.get_or_insert(captures[1].to_lowercase());
+        }
+        if let Some(captures) = TESTED.captures(line)
+            && let Ok(index @ 1..) = captures[1].parse::<usize>()
+        {
+            inference.arg(index).optional = true;
+        }
+        if ALL.is_match(line) {
+            inference.rest = true;
+        }
+
+        if let Some(captures) = GETOPTS.captures(line) {
+            let letters = captures[1].chars().collect::<Vec<_>>();
+            for (i, letter) in letters.iter().enumerate() {
+                if letter.is_ascii_alphabetic() {
+                    inference
+                        .flags
+                        .insert(format!("-{letter}"), letters.get(i + 1) == Some(&':'));
+                }
+            }
+        }
+
+        let command = SUBSTITUTION.replace_all(line, "");
+        let redirected = |line: &str| line.contains('<') && !line.contains("<(");
+        if (READ.is_match(line) && !redirected(line) && !redirected(loop_end(&lines, number)))
+            || (CAT.is_match(line) && !redirected(line))
+        {
+            inference.stdin = true;
+        }
+        if OUTPUT.is_match(&command) && !command.contains('>') {
+            inference.stdout = true;
+        }
+
+        for captures in EXIT.captures_iter(line) {
+            if let Ok(code @ 1..) = captures[1].parse() {
+                inference.exit_codes.insert(code);
+            }
+        }
+    }
+
+    // Each optional argument makes every later one optional, the shell fills them in order.
+    let mut optional = false;
+    for (index, arg) in inference.args.iter_mut().enumerate() {
+        optional |= arg.optional;
+        arg.optional = optional;
+
+        arg.int = arg.name.as_ref().is_some_and(|name| numeric(&lines, name))
+            || numeric(&lines, &(index + 1).to_string());
+    }
+
+    inference
+}
+
+/// Lines of `case` blocks that parse flags from `$1`, or from the variable of a
+/// `for` loop over the arguments. Their flags are added to `inference`.
+fn flag_parsing(lines: &[String], inference: &mut Inference) -> BTreeSet<usize> {
+    let loops = lines
+        .iter()
+        .filter_map(|x| LOOP.captures(x).map(|x| x[1].to_string()))
+        .collect::<BTreeSet<_>>();
+
+    let mut parsing = BTreeSet::new();
+    let mut number = 0;
+    while number < lines.len() {
+        let subject = CASE.captures(&lines[number]).map(|x| x[1].to_string());
+        if !subject.is_some_and(|x| x == "1" || loops.contains(&x)) {
+            number += 1;
+            continue;
+        }
+
+        let mut depth = 0;
+        let mut branch: Vec<(String, bool)> = Vec::new();
+        while number < lines.len() {
+            let line = &lines[number];
+            parsing.insert(number);
+
+            if line.starts_with("case ") {
+                depth += 1;
+            }
+            if line == "esac" || line.starts_with("esac ") || line.starts_with("esac;") {
+                depth -= 1;
+                if depth == 0 {
+                    break;
+                }
+            }
+
+            let mut body = line.as_str();
+            if depth == 1
+                && !line.starts_with("case ")
+                && let Some(captures) = BRANCH.captures(line)
+                && captures[1].starts_with('-')
+            {
+                branch = captures[1]
+                    .split('|')
+                    .map(|x| x.trim_matches(['"', '\'']))
+                    .filter_map(|x| match x.strip_suffix("=*") {
+                        Some(name) => Some((name.to_string(), true)),
+                        None if x.contains('*') => None,
+                        None => Some((x.to_string(), false)),
+                    })
+                    .collect();
+                body = captures.get(2).map_or("", |x| x.as_str());
+            }
+
+            if VALUE.is_match(body) {
+                branch.iter_mut().for_each(|x| x.1 = true);
+            }
+            for (name, value) in &branch {
+                match FLAG.is_match(name) {
+                    true => {
+                        *inference.flags.entry(name.clone()).or_default() |= *value;
+                    }
+                    false if name == "--" || name == "-" => {}
+                    false if !inference.skipped.contains(name) => inference.skipped.push(name.clone()),
+                    false => {}
+                }
+            }
+            if body.contains(";;") {
+                branch.clear();
+            }
+
+            number += 1;
+        }
+
+        number += 1;
+    }
+
+    parsing
+}
+
+/// The `done` that closes the loop started on `number`, where a `while read` redirects its input.
+fn loop_end(lines: &[String], number: usize) -> &str {
+    if !lines[number].starts_with("while") {
+        return "";
+    }
+
+    let mut depth = 0;
+    for line in &lines[number..] {
+        if line.starts_with("while") || line.starts_with("for ") || line.starts_with("until") {
+            depth += 1;
+        }
+        if line.starts_with("done") {
+            depth -= 1;
+            if depth == 0 {
+                return line;
+            }
+        }
+    }
+
+    ""
+}
+
+/// Whether `name`, a variable or a positional index, is compared or computed as a number.
+fn numeric(lines: &[String], name: &str) -> bool {
+    let name = regex::escape(name);
+    let reference = format!(r#""?\$\{{?{name}\}}?"?"#);
+    let compared = Regex::new(&format!(
+        r"{reference}\s+-(eq|ne|gt|ge|lt|le)\b|-(eq|ne|gt|ge|lt|le)\s+{reference}"
+    ))
+    .unwrap();
+    let computed = Regex::new(&format!(r"\$\(\(.*(\b{name}\b|\${name}\b)")).unwrap();
+
+    lines.iter().any(|x| compared.is_match(x) || computed.is_match(x))
+}
+
+impl Inference {
+    fn arg(&mut self, index: usize) -> &mut Arg {
+        if self.args.len() < index {
+            self.args.resize(index, Arg::default());
+        }
+        &mut self.args[index - 1]
+    }
+
+    /// The `#>>>` contract line for the method `name`.
+    pub fn line(&self, name: &str) -> String {
+        let mut names = BTreeSet::new();
+        let mut parameters = Vec::new();
+
+        for (index, arg) in self.args.iter().enumerate() {
+            let mut base = arg.name.clone().unwrap_or_else(|| format!("arg{}", index + 1));
+            if RESERVED.contains(&base.as_str()) {
+                base += "_arg";
+            }
+            let mut name = base.clone();
+            let mut suffix = index + 1;
+            while !names.insert(name.clone()) {
+                name = format!("{base}{suffix}");
+                suffix += 1;
+            }
+
+            let kind = if arg.int { "<int>" } else { "" };
+            let mark = if arg.optional { "?" } else { "!" };
+            parameters.push(format!("{name}{kind}{mark}"));
+        }
+        if self.rest {
+            let name = if self.args.is_empty() { "args" } else { "rest" };
+            parameters.push(format!("{name}...?"));
+        }
+        for (flag, value) in &self.flags {
+            parameters.push(match value {
+                true => format!("{flag}=?"),
+                false => format!("{flag}?"),
+            });
+        }
+
+        let mut sections = Vec::new();
+        if self.stdin {
+            sections.push("stdin?".to_string());
+        }
+        if !parameters.is_empty() {
+            sections.push(format!("({})", parameters.join(", ")));
+        }
+        if self.stdout {
+            sections.push("stdout?".to_string());
+        }
+
+        let mut line = format!("#>>> {name}:");
+        if !sections.is_empty() {
+            line += &format!(" {}", sections.join(" -> "));
+        }
+        if !self.exit_codes.is_empty() {
+            let codes = self.exit_codes.iter().map(u32::to_string).collect::<Vec<_>>();
+            line += &format!(" [{}]", codes.join(", "));
+        }
+
+        line
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::contract::{ArgKind, Contract, Stdin, Stdout};
+
+    #[test]
+    fn positional() {
+        let script = "#!/bin/sh\n# Connects to $9.\nssid=\"$1\"\nband=${2:-5}\n[ \"$3\" -gt 0 ] || exit 3\necho \"$ssid $band\" >&2\nprintf '%s\\n' \"$@\"\nexit 0\n";
+        let line = scan(script).line("connect");
+        assert_eq!(line, "#>>> connect: (ssid!, band?, arg3<int>?, rest...?) -> stdout? [3]");
+
+        let contract = line.parse::<Contract>().unwrap();
+        assert_eq!(contract.min_args(), 1);
+        assert_eq!(contract.args()[2].kind(), ArgKind::Int);
+        assert_eq!(contract.error_codes(), &vec![3]);
+        assert_eq!(contract.stdout(), &Stdout::Optional);
+    }
+
+    #[test]
+    fn flags() {
+        let script = r#"#!/bin/sh
+while [ $# -gt 0 ]; do
+    case "$1" in
+        -v|--verbose) verbose=1 ;;
+        --band)
+            band="$2"
+            shift
+            ;;
+        --name=*) name="${1#*=}" ;;
+        --dry-run) dry=1 ;;
+        --) shift; break ;;
+        *) break ;;
+    esac
+    shift
+done
+while getopts "qo:" opt; do :; done
+while read -r line; do
+    echo "$line" > /tmp/log
+done
+count=$1
+echo $((count + 1))
+"#;
+        let inference = scan(script);
+        assert_eq!(inference.skipped, vec!["--dry-run".to_string()]);
+
+        let line = inference.line("tune");
+        assert_eq!(
+            line,
+            "#>>> tune: stdin? -> (count<int>!, --band=?, --name=?, --verbose?, -o=?, -q?, -v?) -> stdout?"
+        );
+
+        let contract = line.parse::<Contract>().unwrap();
+        assert_eq!(contract.stdin(), &Stdin::Optional);
+        assert!(contract.flags().iter().any(|x| x.name() == "--band" && x.required_value()));
+    }
+
+    #[test]
+    fn quiet() {
+        let script = "#!/bin/sh\nwhile read -r line; do\n  touch \"$line\"\ndone < /etc/list\nlogger done >/dev/null\n";
+        assert_eq!(scan(script).line("touch_all"), "#>>> touch_all:");
+        assert!("#>>> touch_all:".parse::<Contract>().is_ok());
+
+        let line = scan("for x; do\n  case $x in\n    -f) f=1 ;;\n  esac\ndone\ncat | wc -l\nstdin=$1\n").line("count");
+        assert_eq!(line, "#>>> count: stdin? -> (stdin_arg!, -f?) -> stdout?");
+        assert!(line.parse::<Contract>().is_ok());
+    }
+
+    #[test]
+    fn colliding_names() {
+        let line = scan("#!/bin/sh
+x3=$1
+x=$2
+x=$3
+").line("pick");
+        assert_eq!(line, "#>>> pick: (x3!, x!, x4!)");
+        assert!(line.parse::<Contract>().is_ok());
+    }
+}
@@ -0,0 +1,456 @@
+use crate::argv::{self, Token};
+use crate::audit;
+use crate::config::state_dir;
+use crate::contract::{ArgKind, Contract};
+use crate::method::Method;
+use crate::stream::Stream;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+use std::fs::{self, OpenOptions};
+use std::io::{self, ErrorKind, Write};
+use std::path::PathBuf;
+use std::sync::{LazyLock, Mutex};
+
+static FLAG: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"^--?[a-zA-Z]\w*$").unwrap());
+
+/// The observation of the current call, kept until it exits.
+static PENDING: Mutex<Option<(String, Observation)>> = Mutex::new(None);
+
+/// Set by `irnix learn start`, removed by `irnix learn stop`.
+#[derive(Serialize, Deserialize, Debug, Default, PartialEq)]
+pub struct Session {
+    /// Only methods under this object or method prefix are observed.
+    pub prefix: Option<String>,
+    /// Unix time after which nothing is observed anymore.
+    pub until: Option<u64>,
+}
+
+impl Session {
+    fn covers(&self, method: &str) -> bool {
+        self.until.is_none_or(|x| audit::now() < x)
+            && self
+                .prefix
+                .as_ref()
+                .is_none_or(|x| method == x || method.starts_with(&format!("{x}.")))
+    }
+}
+
+/// One call of an uncontracted method. Values are replaced by a placeholder of their type,
+/// `1`, `1.5` or `x`, so that the argv keeps its shape but none of its data.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+pub struct Observation {
+    pub argv: Vec<String>,
+    /// `pipe`, `file` or `socket` when stdin was provided.
+    pub stdin: Option<String>,
+    pub stdout_piped: bool,
+    pub exit_code: i32,
+}
+
+fn dir() -> PathBuf {
+    state_dir().join("learn")
+}
+
+fn session_file() -> PathBuf {
+    dir().join("session.json")
+}
+
+pub fn observations_file(method: &str) -> PathBuf {
+    dir().join(format!("{method}.jsonl"))
+}
+
+pub fn start(session: &Session) -> io::Result<()> {
+    fs::create_dir_all(dir())?;
+    fs::write(session_file(), serde_json::to_vec(session)?)
+}
+
+/// Ends the session, `false` if none was running.
+pub fn stop() -> io::Result<bool> {
+    match fs::remove_file(session_file()) {
+        Ok(()) => Ok(true),
+        Err(err) if err.kind() == ErrorKind::NotFound => Ok(false),
+        Err(err) => Err(err),
+    }
+}
+
+fn session() -> Option<Session> {
+    serde_json::from_slice(&fs::read(session_file()).ok()?).ok()
+}
+
+/// Starts observing the call if a session covers the method. The method is supervised
+/// so that its exit code is known.
+pub fn begin(method: &mut Method) {
+    if method.contract().is_some() || !session().is_some_and(|x| x.covers(&method.dotted_name)) {
+        return;
+    }
+
+    let stdin = match method.stdin.stream() {
+        Stream::Pipe => Some("pipe"),
+        Stream::File => Some("file"),
+        Stream::Socket => Some("socket"),
+        _ => None,
+    };
+    let observation = Observation {
+        argv: shape(&method.args),
+        stdin: stdin.map(String::from),
+        stdout_piped: method.stdout.is_pipeline(),
+        exit_code: 0,
+    };
+
+    method.supervision.get_or_insert_default();
+    *PENDING.lock().unwrap() = Some((method.dotted_name.clone(), observation));
+}
+
+/// Appends the pending observation with the exit code of the call.
+pub fn finish(exit_code: i32) {
+    let Some((method, mut observation)) = PENDING.lock().unwrap().take() else {
+        return;
+    };
+    observation.exit_code = exit_code;
+
+    let path = observations_file(&method);
+    let mut line = serde_json::to_vec(&observation).unwrap();
+    line.push(b'\n');
+
+    // Learning must never fail the call.
+    let _ = fs::create_dir_all(dir()).and_then(|_| {
+        OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)?
+            .write_all(&line)
+    });
+}
+
+pub fn observations(method: &str) -> io::Result<Vec<Observation>> {
+    let text = match fs::read_to_string(observations_file(method)) {
+        Ok(text) => text,
+        Err(err) if err.kind() == ErrorKind::NotFound => return Ok(vec![]),
+        Err(err) => return Err(err),
+    };
+
+    Ok(text.lines().filter_map(|x| serde_json::from_str(x).ok()).collect())
+}
+
+/// The argv with every value replaced by a placeholder, split like [`argv::parse`] does.
+fn shape(argv: &[String]) -> Vec<String> {
+    let mut terminated = false;
+
+    argv.iter()
+        .map(|x| match classify(x, terminated) {
+            Token::Value(value) => placeholder(value).to_string(),
+            Token::Terminator => {
+                terminated = true;
+                x.clone()
+            }
+            Token::Flag(name, Some(value)) => format!("{name}={}", placeholder(value)),
+            Token::Flag(name, None) => name.to_string(),
+        })
+        .collect()
+}
+
+/// [`argv::token`], with negative numbers as values since there is no contract to tell.
+fn classify(token: &str, terminated: bool) -> Token<'_> {
+    match argv::token(token, terminated) {
+        Token::Flag(..) if ArgKind::Num.accepts(token) => Token::Value(token),
+        token => token,
+    }
+}
+
+fn placeholder(value: &str) -> &'static str {
+    match (ArgKind::Int.accepts(value), ArgKind::Num.accepts(value)) {
+        (true, _) => "1",
+        (false, true) => "1.5",
+        _ => "x",
+    }
+}
+
+/// The kind that accepts every value.
+fn kind<'a>(values: impl Iterator<Item = &'a String>) -> ArgKind {
+    let mut kind = ArgKind::Int;
+    for value in values {
+        if !ArgKind::Int.accepts(value) {
+            kind = ArgKind::Num;
+        }
+        if !ArgKind::Num.accepts(value) {
+            return ArgKind::Text;
+        }
+    }
+    kind
+}
+
+/// A token of an observed argv, before flags are told apart from their values.
+enum Observed {
+    Flag { name: String, inline: bool },
+    Value(String),
+    Terminator,
+}
+
+fn tokens(argv: &[String]) -> Vec<Observed> {
+    let mut tokens = Vec::new();
+    let mut terminated = false;
+
+    for token in argv {
+        match classify(token, terminated) {
+            Token::Value(value) => tokens.push(Observed::Value(value.to_string())),
+            Token::Terminator => {
+                terminated = true;
+                tokens.push(Observed::Terminator);
+            }
+            // A cluster of short switches, `-abc`.
+            Token::Flag(name, None) if !name.starts_with("--") && name.len() > 2 => {
+                tokens.extend(name[1..].chars().map(|x| Observed::Flag {
+                    name: format!("-{x}"),
+                    inline: false,
+                }));
+            }
+            Token::Flag(name, value) => tokens.push(Observed::Flag {
+                name: name.to_string(),
+                inline: value.is_some(),
+            }),
+        }
+    }
+
+    tokens
+}
+
+/// The tightest contract consistent with the observations.
+#[derive(Debug, PartialEq)]
+pub struct Suggestion {
+    pub line: String,
+    /// Flags the grammar cannot declare and guesses worth a look.
+    pub notes: Vec<String>,
+}
+
+pub fn suggest(name: &str, observations: &[Observation]) -> Suggestion {
+    let calls = observations.iter().map(|x| tokens(&x.argv)).collect::<Vec<_>>();
+    let mut notes = Vec::new();
+
+    // A flag takes a value if it was ever given one with `=`, and none if it was ever
+    // followed by nothing or by another flag. Otherwise it takes one only if that makes
+    // the calls with it have as many arguments as the calls without it.
+    let mut inline = BTreeSet::new();
+    let mut bare = BTreeSet::new();
+    let mut names = BTreeSet::new();
+    for call in &calls {
+        for (i, token) in call.iter().enumerate() {
+            let Observed::Flag { name, inline: attached } = token else {
+                continue;
+            };
+            names.insert(name.clone());
+            if *attached {
+                inline.insert(name.clone());
+            } else if !matches!(call.get(i + 1), Some(Observed::Value(_))) {
+                bare.insert(name.clone());
+            }
+        }
+    }
+
+    let mut valued = inline.clone();
+    for name in names.iter().filter(|x| !inline.contains(*x) && !bare.contains(*x)) {
+        let without = calls
+            .iter()
+            .filter(|call| !call.iter().any(|x| matches!(x, Observed::Flag { name: n, .. } if n == name)))
+            .map(|call| positional(call, &valued).len())
+            .collect::<BTreeSet<_>>();
+
+        let mut with_value = valued.clone();
+        with_value.insert(name.clone());
+        let fits = |valued: &BTreeSet<String>| {
+            calls
+                .iter()
+                .all(|call| without.contains(&positional(call, valued).len()))
+        };
+
+        if !without.is_empty() && fits(&with_value) && !fits(&valued) {
+            valued = with_value;
+            notes.push(format!("{name} was always followed by a value, it is declared with one"));
+        } else {
+            notes.push(format!("{name} was always followed by a value, it is declared as a switch"));
+        }
+    }
+
+    let mut parameters = Vec::new();
+
+    let positionals = calls.iter().map(|x| positional(x, &valued)).collect::<Vec<_>>();
+    let min = positionals.iter().map(Vec::len).min().unwrap_or(0);
+    let max = positionals.iter().map(Vec::len).max().unwrap_or(0);
+    let kind_of = |range: std::ops::Range<usize>| {
+        let kind = kind(positionals.iter().flat_map(|x| x.iter().skip(range.start).take(range.len())));
+        match kind {
+            ArgKind::Text => String::new(),
+            kind => format!("<{}>", kind.as_str()),
+        }
+    };
+    for i in 0..min {
+        parameters.push(format!("arg{}{}!", i + 1, kind_of(i..i + 1)));
+    }
+    match max - min {
+        0 => {}
+        1 => parameters.push(format!("arg{}{}?", min + 1, kind_of(min..max))),
+        more => parameters.push(format!("rest{}{{0,{more}}}", kind_of(min..max))),
+    }
+
+    for name in &names {
+        if !FLAG.is_match(name) {
+            notes.push(format!("{name} can not be declared by a contract"));
+            continue;
+        }
+
+        let counts = calls
+            .iter()
+            .map(|call| {
+                call.iter()
+                    .filter(|x| matches!(x, Observed::Flag { name: n, .. } if n == name))
+                    .count()
+            })
+            .collect::<Vec<_>>();
+        let required = counts.iter().all(|x| *x > 0);
+        let value = valued.contains(name);
+        let repeated = value && counts.iter().any(|x| *x > 1);
+
+        let mark = match (required, repeated) {
+            (true, true) => "+",
+            (false, true) => "*",
+            (true, false) => "!",
+            (false, false) => "?",
+        };
+        parameters.push(format!("{name}{}{mark}", if value { "=" } else { "" }));
+    }
+
+    let mut sections = Vec::new();
+    let stdins = observations.iter().filter_map(|x| x.stdin.as_deref()).collect::<BTreeSet<_>>();
+    if !stdins.is_empty() {
+        let mode = match stdins.iter().collect::<Vec<_>>()[..] {
+            [mode] if Stream::from_mode(mode).is_some() => format!(":{mode}"),
+            _ => String::new(),
+        };
+        let mark = match observations.iter().all(|x| x.stdin.is_some()) {
+            true => "!",
+            false => "?",
+        };
+        sections.push(format!("stdin{mode}{mark}"));
+    }
+    if !parameters.is_empty() {
+        sections.push(format!("({})", parameters.join(", ")));
+    }
+    if observations.iter().any(|x| x.stdout_piped) {
+        sections.push("stdout?".to_string());
+    }
+
+    let mut line = format!("#>>> {name}:");
+    if !sections.is_empty() {
+        line += &format!(" {}", sections.join(" -> "));
+    }
+    let codes = observations
+        .iter()
+        .filter(|x| x.exit_code != 0)
+        .map(|x| x.exit_code)
+        .collect::<BTreeSet<_>>();
+    if !codes.is_empty() {
+        let codes = codes.iter().map(i32::to_string).collect::<Vec<_>>();
+        line += &format!(" [{}]", codes.join(", "));
+    }
+
+    // What the contract validator would refuse of the very calls it was learned from.
+    if let Ok(contract) = line.parse::<Contract>() {
+        let refused = observations
+            .iter()
+            .filter(|x| argv::parse(&x.argv, &contract).is_err())
+            .count();
+        if refused > 0 {
+            notes.push(format!("{refused} of the observed calls do not match it"));
+        }
+    }
+
+    Suggestion { line, notes }
+}
+
+/// The positional values of a call when the flags in `valued` take the token after them.
+fn positional(call: &[Observed], valued: &BTreeSet<String>) -> Vec<String> {
+    let mut values = Vec::new();
+    let mut skip = false;
+
+    for token in call {
+        match token {
+            Observed::Value(_) if skip => skip = false,
+            Observed::Value(value) => values.push(value.clone()),
+            Observed::Flag { name, inline } => skip = !inline && valued.contains(name),
+            Observed::Terminator => skip = false,
+        }
+    }
+
+    values
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn observation(argv: &[&str], stdin: Option<&str>, exit_code: i32) -> Observation {
+        Observation {
+            argv: shape(&argv.iter().map(|x| x.to_string()).collect::<Vec<_>>()),
+            stdin: stdin.map(String::from),
+            stdout_piped: false,
+            exit_code,
+        }
+    }
+
+    #[test]
+    fn shapes() {
+        let argv = ["deploy", "--env=prod", "-v", "3", "-2.5", "--", "--force"].map(String::from);
+        assert_eq!(shape(&argv), ["x", "--env=x", "-v", "1", "1.5", "--", "x"]);
+    }
+
+    #[test]
+    fn sessions() {
+        let session = Session {
+            prefix: Some("net.wifi".into()),
+            until: None,
+        };
+        assert!(session.covers("net.wifi.scan"));
+        assert!(!session.covers("net.wifis.scan"));
+        assert!(
+            !Session {
+                prefix: None,
+                until: Some(1)
+            }
+            .covers("net.wifi.scan")
+        );
+    }
+
+    #[test]
+    fn suggestions() {
+        let observations = [
+            observation(&["--env", "prod", "1.2", "a"], Some("pipe"), 0),
+            observation(&["--env=stage", "-v", "1.3"], Some("pipe"), 3),
+            observation(&["1.4", "a", "b", "--force"], Some("pipe"), 0),
+            observation(&["--env", "prod", "--tag=a", "--tag=b", "2"], Some("pipe"), 64),
+        ];
+
+        let suggestion = suggest("deploy", &observations);
+        assert_eq!(
+            suggestion.line,
+            "#>>> deploy: stdin:pipe! -> (arg1<num>!, rest{0,2}, --env=?, --force?, --tag=*, -v?) [3, 64]"
+        );
+        assert_eq!(suggestion.notes, ["-v was always followed by a value, it is declared as a switch"]);
+        assert!(suggestion.line.parse::<Contract>().is_ok());
+
+        let observations = [
+            observation(&["--force", "1.2"], None, 0),
+            observation(&["1.3"], Some("file"), 0),
+            observation(&["--dry-run", "1.3"], None, 0),
+        ];
+        let suggestion = suggest("deploy", &observations);
+        assert_eq!(suggestion.line, "#>>> deploy: stdin:file? -> (arg1<num>!, --force?)");
+        assert_eq!(
+            suggestion.notes,
+            [
+                "--dry-run was always followed by a value, it is declared as a switch",
+                "--force was always followed by a value, it is declared as a switch",
+                "--dry-run can not be declared by a contract",
+                "1 of the observed calls do not match it",
+            ]
+        );
+    }
+}
@@ -0,0 +1,249 @@
+use crate::supervisor::parse_duration;
+use std::collections::HashSet;
+use std::fs::{self, File, OpenOptions};
+use std::io;
+use std::os::fd::AsRawFd;
+use std::os::unix::fs::{FileExt, MetadataExt, OpenOptionsExt};
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, Instant};
+
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// What a `@lock` excludes: other calls of the method, or of every method of its object.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum LockScope {
+    Method,
+    Object,
+}
+
+/// How long to wait for a lock held by another call.
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+pub enum LockWait {
+    #[default]
+    Block,
+    FailFast,
+    Timeout(Duration),
+}
+
+/// Parses `wait`, `fail` or a duration such as `30s`. A zero duration fails fast.
+pub fn parse_wait(value: &str) -> Result<LockWait, String> {
+    match value {
+        "wait" => Ok(LockWait::Block),
+        "fail" => Ok(LockWait::FailFast),
+        _ => parse_duration(value).map(|x| {
+            if x.is_zero() {
+                LockWait::FailFast
+            } else {
+                LockWait::Timeout(x)
+            }
+        }),
+    }
+}
+
+/// Takes an exclusive `flock` on `path`, returns `None` if it stays held by someone else.
+///
+/// The descriptor is not closed on exec, so the lock is held until the method exits. Nested
+/// calls skip the locks their callers hold, see [`held_by_caller`].
+pub fn acquire(path: &Path, wait: LockWait, holder: &str) -> io::Result<Option<File>> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+
+    // Never follow a link planted in place of the lock file.
+    let file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .custom_flags(libc::O_NOFOLLOW)
+        .mode(0o600)
+        .open(path)?;
+
+    let start = Instant::now();
+    loop {
+        let operation = match wait {
+            LockWait::Block => libc::LOCK_EX,
+            _ => libc::LOCK_EX | libc::LOCK_NB,
+        };
+
+        if unsafe { libc::flock(file.as_raw_fd(), operation) } == 0 {
+            break;
+        }
+
+        let err = io::Error::last_os_error();
+        match (err.kind(), wait) {
+            (io::ErrorKind::Interrupted, _) => continue,
+            (io::ErrorKind::WouldBlock, LockWait::Timeout(timeout)) if start.elapsed() < timeout => {
+                thread::sleep(POLL_INTERVAL)
+            }
+            (io::ErrorKind::WouldBlock, _) => return Ok(None),
+            _ => return Err(err),
+        }
+    }
+
+    if unsafe { libc::fcntl(file.as_raw_fd(), libc::F_SETFD, 0) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    // The file is not truncated, a longer holder written before is blanked out instead.
+    let line = format!("{} {holder}\n", std::process::id());
+    let length = (file.metadata()?.len() as usize).max(line.len());
+    file.write_all_at(format!("{line:<length$}").as_bytes(), 0)?;
+
+    Ok(Some(file))
+}
+
+/// The `(device, inode)` of every file with a `flock`, read from `/proc/locks`. Probing
+/// with a lock of our own would make a concurrent `fail` acquire fail.
+fn flocked() -> io::Result<HashSet<(u64, u64)>> {
+    let locks = fs::read_to_string("/proc/locks")?;
+
+    // `1: FLOCK  ADVISORY  WRITE 1234 fe:00:1220810 0 EOF`, waiters have `->` before FLOCK.
+    Ok(locks
+        .lines()
+        .filter_map(|line| {
+            let fields = line.split_whitespace().collect::<Vec<_>>();
+            if fields.get(1) != Some(&"FLOCK") {
+                return None;
+            }
+
+            let mut id = fields.get(5)?.split(':');
+            let major = u32::from_str_radix(id.next()?, 16).ok()?;
+            let minor = u32::from_str_radix(id.next()?, 16).ok()?;
+            Some((libc::makedev(major, minor), id.next()?.parse().ok()?))
+        })
+        .collect())
+}
+
+/// Whether the `pid` written to `path` by [`acquire`] is an ancestor of this process,
+/// that is a call this one is nested in.
+pub fn held_by_caller(path: &Path) -> bool {
+    let holder = fs::read_to_string(path)
+        .ok()
+        .and_then(|x| x.split_whitespace().next()?.parse::<u32>().ok());
+    let Some(holder) = holder else {
+        return false;
+    };
+
+    let mut pid = std::os::unix::process::parent_id();
+    while pid > 1 {
+        if pid == holder {
+            return true;
+        }
+
+        pid = match parent(pid) {
+            Some(parent) => parent,
+            None => return false,
+        };
+    }
+
+    false
+}
+
+/// The parent of `pid`, read from `/proc`. The command name before it may contain spaces.
+fn parent(pid: u32) -> Option<u32> {
+    let stat = fs::read_to_string(format!("/proc/{pid}/stat")).ok()?;
+    stat.rsplit_once(')')?.1.split_whitespace().nth(1)?.parse().ok()
+}
+
+/// The lock files under `dir` that are currently held, with the `pid method` of the holder.
+pub fn held(dir: &Path) -> io::Result<Vec<(PathBuf, String)>> {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(vec![]),
+        Err(err) => return Err(err),
+    };
+
+    let locked = flocked()?;
+    let mut held = Vec::new();
+    for entry in entries {
+        let path = entry?.path();
+        if path.extension().is_none_or(|x| x != "lock") {
+            continue;
+        }
+
+        let metadata = fs::symlink_metadata(&path)?;
+        if !locked.contains(&(metadata.dev(), metadata.ino())) {
+            continue;
+        }
+
+        held.push((path.clone(), fs::read_to_string(&path)?.trim().to_string()));
+    }
+
+    held.sort();
+    Ok(held)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::temp_dir::TempDir;
+
+    #[test]
+    fn wait() {
+        assert_eq!(parse_wait("wait"), Ok(LockWait::Block));
+        assert_eq!(parse_wait("fail"), Ok(LockWait::FailFast));
+        assert_eq!(parse_wait("0"), Ok(LockWait::FailFast));
+        assert_eq!(
+            parse_wait("2m"),
+            Ok(LockWait::Timeout(Duration::from_secs(120)))
+        );
+        assert!(parse_wait("later").is_err());
+    }
+
+    #[test]
+    fn exclusion() {
+        let dir = TempDir::new("lock");
+        let path = dir.join("sys.backup.lock");
+
+        let lock = acquire(&path, LockWait::FailFast, "sys.backup.run").unwrap();
+        assert!(lock.is_some());
+
+        // flock locks belong to the open file, so a second open conflicts even in one process.
+        assert!(acquire(&path, LockWait::FailFast, "sys.backup.run").unwrap().is_none());
+        assert!(
+            acquire(&path, LockWait::Timeout(Duration::from_millis(100)), "sys.backup.run")
+                .unwrap()
+                .is_none()
+        );
+
+        let locks = held(&dir).unwrap();
+        assert_eq!(locks.len(), 1);
+        assert!(locks[0].1.ends_with(" sys.backup.run"));
+
+        drop(lock);
+        assert!(acquire(&path, LockWait::FailFast, "sys.backup.run").unwrap().is_some());
+        assert!(held(&dir).unwrap().is_empty());
+    }
+
+    #[test]
+    fn caller() {
+        let dir = TempDir::new("lock-caller");
+        let path = dir.join("sys.backup.lock");
+
+        fs::write(&path, format!("{} sys.backup.run\n", std::os::unix::process::parent_id())).unwrap();
+        assert!(held_by_caller(&path));
+
+        fs::write(&path, format!("{} sys.backup.run\n", std::process::id())).unwrap();
+        assert!(!held_by_caller(&path));
+
+        fs::write(&path, "").unwrap();
+        assert!(!held_by_caller(&path));
+        assert!(!held_by_caller(&dir.join("sys.restore.lock")));
+    }
+
+    #[test]
+    fn holder() {
+        let dir = TempDir::new("lock-holder");
+        let path = dir.join("sys.backup.lock");
+
+        drop(acquire(&path, LockWait::FailFast, "sys.backup.run_everything").unwrap());
+        let _lock = acquire(&path, LockWait::FailFast, "sys.backup.run").unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap().trim(), format!("{} sys.backup.run", std::process::id()));
+
+        let link = dir.join("sys.restore.lock");
+        std::os::unix::fs::symlink(&path, &link).unwrap();
+        assert!(acquire(&link, LockWait::FailFast, "sys.restore.run").is_err());
+    }
+}
@@ -1,18 +1,47 @@
-use crate::commands::commands::Commands;
-use crate::config::unwrap_namespace;
+use crate::commands::commands::{CacheCommands, Commands, LearnCommands, StateCommands};
+use crate::commands::log::Filter;
+use crate::config::{
+    audit_log, runtime_dir, ssh_program, unwrap_error_format, unwrap_namespace, unwrap_remote_namespace,
+};
+use crate::error::{Error, ErrorCode, ErrorFormat};
 use crate::handlers::{Handle, MainHandler};
 use crate::method::Method;
+use crate::mock::Mocks;
+use crate::remote::Remote;
+use crate::stream::Input;
 use clap::CommandFactory;
 use clap::Parser;
-use std::io::stdin;
-use std::process::exit;
+use std::env;
+use std::path::PathBuf;
 
+mod argv;
+mod audit;
+mod cache;
 mod commands;
 mod config;
 mod contract;
 mod contract_tokens;
+mod daemon;
+mod error;
+mod fuzz;
 mod handlers;
+mod header;
+mod hooks;
+mod index;
+mod infer;
+mod learn;
+mod lock;
 mod method;
+mod mock;
+mod remote;
+mod state;
+mod stream;
+mod strictness;
+mod suite;
+mod supervisor;
+#[cfg(test)]
+mod temp_dir;
+mod watch;
 
 #[derive(Parser)]
 #[command(
@@ -25,31 +54,198 @@ mod method;
 pub struct Cli {
     #[command(subcommand)]
     pub(crate) command: Commands,
+
+    #[arg(
+        long,
+        global = true,
+        value_enum,
+        help = "Format of irnix errors on stderr. Defaults to IRNIX_ERROR_FORMAT or text"
+    )]
+    pub(crate) error_format: Option<ErrorFormat>,
+
+    #[arg(
+        long,
+        global = true,
+        help = "Read the namespace directly instead of through the index in the cache dir"
+    )]
+    pub(crate) no_cache: bool,
 }
 
 fn main() {
-    match Cli::parse().command {
+    let cli = Cli::parse();
+    error::set_format(unwrap_error_format(cli.error_format));
+
+    match cli.command {
         Commands::Execute {
             args,
             method,
             namespace,
+            no_stdin_check,
+            strict,
+            stdin_file,
+            lock_wait,
+            remote: remote_host,
+            remote_namespace,
+            supervision,
+            mocks,
         } => {
-            let method = Method::new(method, args, stdin(), unwrap_namespace(namespace));
+            let (host, method) = remote::split(&method);
+            let host = remote_host.or(host.map(String::from));
+            let namespace = unwrap_namespace(namespace);
+            if !cli.no_cache {
+                // A namespace that cannot be indexed is reported by resolution.
+                index::set(index::open(&namespace));
+            }
+            let method = Method::new(method.to_string(), args, namespace);
 
             match method {
                 Ok(mut method) => {
-                    MainHandler::handle(&mut method);
+                    if let Some(host) = host {
+                        if host.is_empty() || host.starts_with('-') {
+                            Error::new(ErrorCode::InvalidName, "The remote host is not valid")
+                                .token(host)
+                                .exit();
+                        }
+
+                        method.remote = Some(Remote {
+                            host,
+                            namespace: unwrap_remote_namespace(remote_namespace, &method.namespace),
+                            ssh: ssh_program(),
+                        });
+                    }
+                    method.stdin_check = !no_stdin_check;
+                    method.strict = strict;
+                    method.caller = env::var("IRNIX_CALLER").ok().filter(|x| !x.is_empty());
+                    // Only the calls a mocked method makes inherit the mocks of the run.
+                    method.mocks = mocks.or_else(|| {
+                        method.caller.as_ref()?;
+                        env::var_os("IRNIX_MOCKS").filter(|x| !x.is_empty()).map(PathBuf::from)
+                    });
+                    if method.caller.is_some() {
+                        method.locks = env::var_os("IRNIX_LOCKS")
+                            .map(|x| env::split_paths(&x).collect())
+                            .unwrap_or_default();
+                    }
+                    method.lock_wait = lock_wait;
+                    if let Some(path) = stdin_file {
+                        method.stdin = Input::File(path);
+                    }
+                    method.supervision = supervision.into();
+                    audit::begin(&method);
+                    if let Err(err) = MainHandler::handle(&mut method) {
+                        err.method(method.dotted_name).exit();
+                    }
+                    audit::passed(&method);
                     method.execute();
                 }
-                Err(message) => {
-                    eprintln!("{message}");
-                    exit(1);
-                }
+                Err(err) => err.exit(),
             }
         }
         Commands::Completions { shell } => {
             clap_complete::generate(shell, &mut Cli::command(), "irnix", &mut std::io::stdout())
         }
-        Commands::Methods { namespace } => commands::methods::execute(unwrap_namespace(namespace)),
+        Commands::Methods { namespace } => {
+            commands::methods::execute(unwrap_namespace(namespace), !cli.no_cache)
+        }
+        Commands::Check { prefix, namespace } => {
+            commands::check::execute(unwrap_namespace(namespace), prefix, !cli.no_cache)
+        }
+        Commands::Infer {
+            method,
+            namespace,
+            append,
+        } => commands::infer::execute(unwrap_namespace(namespace), method, append),
+        Commands::Fuzz {
+            method,
+            namespace,
+            count,
+            seed,
+            replay,
+            no_exec,
+            timeout,
+        } => commands::fuzz::execute(
+            unwrap_namespace(namespace),
+            method,
+            commands::fuzz::Options {
+                count,
+                seed,
+                replay,
+                run: !no_exec,
+                timeout,
+            },
+            !cli.no_cache,
+        ),
+        Commands::Test {
+            prefix,
+            namespace,
+            format,
+            mocks,
+            mock_namespace,
+        } => commands::test::execute(
+            unwrap_namespace(namespace),
+            prefix,
+            format,
+            Mocks {
+                scripts: mocks,
+                namespace: mock_namespace.map(|x| std::path::absolute(&x).unwrap_or(x)),
+            },
+            !cli.no_cache,
+        ),
+        Commands::Log {
+            method,
+            since,
+            until,
+            status,
+            json,
+            file,
+        } => match file.or_else(audit_log) {
+            Some(log) => commands::log::execute(
+                log,
+                Filter {
+                    method,
+                    since,
+                    until,
+                    status,
+                },
+                json,
+            ),
+            None => Error::new(
+                ErrorCode::AuditLogDisabled,
+                "The audit log is disabled. Set IRNIX_AUDIT_LOG or pass --file",
+            )
+            .exit(),
+        },
+        Commands::Daemon { namespace, socket } => {
+            let socket = match socket {
+                Some(socket) => socket,
+                None => runtime_dir().unwrap_or_else(|err| err.exit()).join("daemon.sock"),
+            };
+            if let Err(err) = daemon::serve(unwrap_namespace(namespace), &socket, !cli.no_cache) {
+                err.exit();
+            }
+        }
+        Commands::Locks => commands::locks::execute(),
+        Commands::Cache { command } => match command {
+            CacheCommands::Clear { prefix } => commands::cache::clear(prefix),
+        },
+        Commands::State { command } => match command {
+            StateCommands::Show { object } => commands::state::show(object),
+            StateCommands::Reset { object } => commands::state::reset(object),
+        },
+        Commands::Learn { command } => match command {
+            LearnCommands::Start { prefix, duration } => commands::learn::start(prefix, duration),
+            LearnCommands::Stop => commands::learn::stop(),
+            LearnCommands::Suggest { method } => commands::learn::suggest(method),
+        },
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn cli() {
+        Cli::command().debug_assert();
     }
 }
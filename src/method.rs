@@ -1,11 +1,31 @@
+use crate::argv::ParsedArgs;
+use crate::audit;
+use crate::cache::Cache;
+use crate::config::cache_dir;
+use crate::hooks;
+use crate::learn;
+use crate::lock::LockWait;
+use crate::remote::{Remote, SSH_EXIT_CODE};
 use crate::contract::Contract;
+use crate::error::{Error, ErrorCode};
+use crate::stream::{Input, Stream};
+use crate::strictness::{self, Strictness};
+use crate::supervisor::{self, Supervision};
 use regex::Regex;
 use std::collections::HashMap;
-use std::io::{IsTerminal, Stdin};
+use std::fs::File;
+use std::io::Write;
+use std::sync::LazyLock;
+use std::time::Duration;
+use std::{env, io};
 use std::os::unix::process::CommandExt;
-use std::path::PathBuf;
+use std::path::{self, PathBuf};
 use std::process::{exit, Command, Stdio};
 
+static INSTANCE: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"^[\w-]+$").unwrap());
+static NAME: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"^[\w-]+\.([\w-]+\.?)+[\w-]$").unwrap());
+
 pub(crate) struct Object {
     pub(crate) path: PathBuf,
     pub(crate) name: String,
@@ -13,31 +33,72 @@ pub(crate) struct Object {
 }
 
 pub(crate) struct Method {
-    pub(crate) stdin: Option<Stdin>,
+    pub(crate) stdin: Input,
+    pub(crate) stdout: Stream,
+    /// Cleared by `--no-stdin-check` to skip the stdin requirements of the contract.
+    pub(crate) stdin_check: bool,
     pub(crate) name: String,
+    pub(crate) dotted_name: String,
     pub(crate) path: PathBuf,
     pub(crate) args: Vec<String>,
+    pub(crate) parsed: Option<ParsedArgs>,
     pub(crate) object: Object,
+    pub(crate) namespace: PathBuf,
+    pub(crate) supervision: Option<Supervision>,
+    /// `.after` hooks to run once the method exits.
+    pub(crate) after_hooks: Vec<PathBuf>,
+    /// Overrides the `@lock_wait` of the contract.
+    pub(crate) lock_wait: Option<LockWait>,
+    /// The `@lock` taken for the call. Kept open, and inherited, until the method exits.
+    pub(crate) lock: Option<File>,
+    /// The lock files held by the calls this one is nested in, and the one it takes.
+    /// Exported as `IRNIX_LOCKS`, so that a nested call does not wait for its caller.
+    pub(crate) locks: Vec<PathBuf>,
+    /// Set by `host:object.method` or `--remote` to run the method over ssh.
+    pub(crate) remote: Option<Remote>,
+    /// Refuse deprecated and uncontracted methods, whatever the strictness policy.
+    pub(crate) strict: bool,
+    /// The method this call comes from, taken from `IRNIX_CALLER`.
+    pub(crate) caller: Option<String>,
+    /// Set by `object#instance.method` to keep a separate state per instance.
+    pub(crate) instance: Option<String>,
+    /// The state directory of the object, created before the method runs if the object has
+    /// an `.init`, an instance or the contract declares `@state`.
+    pub(crate) state: Option<PathBuf>,
+    /// The mocks file of an `irnix test` run, given by the runner with `--mocks` and passed
+    /// on to the calls the method makes through `IRNIX_MOCKS`.
+    pub(crate) mocks: Option<PathBuf>,
 }
 
 impl Method {
     pub fn new(
         name: String,
         args: Vec<String>,
-        stdin: Stdin,
         namespace: PathBuf,
-    ) -> Result<Method, String> {
+    ) -> Result<Method, Error> {
         let name = name.trim();
 
-        if !Regex::new(r"^[\w-]+\.([\w-]+\.?)+[\w-]$").unwrap().is_match(name) {
-            return Err("The method call does not match the pattern".into());
-        }
+        // `counter#a.inc` calls `counter.inc` on the instance `a`.
+        let (name, instance) = match name.rsplit_once('.').and_then(|(object, method)| {
+            object
+                .rsplit_once('#')
+                .map(|(object, instance)| (format!("{object}.{method}"), instance))
+        }) {
+            Some((_, instance)) if !INSTANCE.is_match(instance) => {
+                return Err(Error::new(ErrorCode::InvalidName, "The instance name is not valid")
+                    .token(instance));
+            }
+            Some((name, instance)) => (name, Some(instance.to_string())),
+            None => (name.to_string(), None),
+        };
+        let name = name.as_str();
 
-        let stdin_result: Option<Stdin>;
-        if stdin.is_terminal() {
-            stdin_result = None;
-        } else {
-            stdin_result = Some(stdin);
+        if !NAME.is_match(name) {
+            return Err(Error::new(
+                ErrorCode::InvalidName,
+                "The method call does not match the pattern",
+            )
+            .token(name));
         }
 
         let entities = name.split(".").collect::<Vec<&str>>();
@@ -49,22 +110,350 @@ impl Method {
         };
 
         Ok(Method {
-            stdin: stdin_result,
+            stdin: Input::stdin(),
+            stdout: Stream::stdout(),
+            stdin_check: true,
             name: entities[entities.len() - 1].into(),
+            dotted_name: name.into(),
             path: namespace.join(entities.join("/")),
             args,
+            parsed: None,
             object,
+            namespace,
+            supervision: None,
+            after_hooks: vec![],
+            lock_wait: None,
+            lock: None,
+            locks: vec![],
+            remote: None,
+            strict: false,
+            caller: None,
+            instance,
+            state: None,
+            mocks: None,
         })
     }
 
+    /// Variables that describe the call: `IRNIX_METHOD`, `IRNIX_OBJECT`, `IRNIX_NAMESPACE`,
+    /// `IRNIX_CALLER` for the calls the method makes, `IRNIX_INSTANCE` and `IRNIX_STATE`
+    /// for objects with state, `IRNIX_MOCKS` under `irnix test --mock` and, once the argv is parsed against the contract,
+    /// `IRNIX_ARG_<name>` for every provided argument and `IRNIX_FLAG_<name>` for every
+    /// provided flag.
+    ///
+    /// A flag without a value is set to the number of times it was given. Arguments
+    /// and flags with several values are set to the values separated by newlines.
+    pub fn environment(&self) -> Vec<(String, String)> {
+        let object = self
+            .dotted_name
+            .rsplit_once('.')
+            .map(|x| x.0)
+            .unwrap_or_default();
+
+        let mut environment = vec![
+            ("IRNIX_METHOD".to_string(), self.dotted_name.clone()),
+            ("IRNIX_OBJECT".to_string(), object.to_string()),
+            ("IRNIX_CALLER".to_string(), self.dotted_name.clone()),
+            (
+                "IRNIX_NAMESPACE".to_string(),
+                path::absolute(&self.namespace)
+                    .unwrap_or(self.namespace.clone())
+                    .to_string_lossy()
+                    .to_string(),
+            ),
+        ];
+
+        if let Some(instance) = &self.instance {
+            environment.push(("IRNIX_INSTANCE".to_string(), instance.clone()));
+        }
+        if let Some(state) = &self.state {
+            environment.push(("IRNIX_STATE".to_string(), state.to_string_lossy().to_string()));
+        }
+        if let Some(mocks) = &self.mocks {
+            environment.push(("IRNIX_MOCKS".to_string(), mocks.to_string_lossy().to_string()));
+        }
+        if let Ok(locks) = env::join_paths(&self.locks)
+            && !locks.is_empty()
+        {
+            environment.push(("IRNIX_LOCKS".to_string(), locks.to_string_lossy().to_string()));
+        }
+
+        if let Some(parsed) = &self.parsed {
+            for (arg, values) in &parsed.args {
+                environment.push((format!("IRNIX_ARG_{arg}"), values.join("\n")));
+            }
+
+            for (flag, values) in &parsed.flags {
+                let name = flag.trim_start_matches('-').replace('-', "_");
+                let value = if values.values.is_empty() {
+                    values.count.to_string()
+                } else {
+                    values.values.join("\n")
+                };
+
+                environment.push((format!("IRNIX_FLAG_{name}"), value));
+            }
+        }
+
+        environment
+    }
+
+    pub fn contract(&self) -> Option<&Contract> {
+        self.object
+            .contracts
+            .as_ref()
+            .and_then(|x| x.get(&self.name))
+    }
+
+    /// The dotted object of the call with its instance, `counter#a`.
+    pub fn object_key(&self) -> String {
+        let object = self.dotted_name.rsplit_once('.').map(|x| x.0).unwrap_or_default();
+
+        match &self.instance {
+            Some(instance) => format!("{object}#{instance}"),
+            None => object.to_string(),
+        }
+    }
+
+    /// The strictness policy of the object, [`Strictness::Strict`] under `--strict`.
+    pub fn strictness(&self) -> Strictness {
+        if self.strict {
+            return Strictness::Strict;
+        }
+
+        let object = self.dotted_name.rsplit_once('.').map(|x| x.0).unwrap_or_default();
+        strictness::resolve(&self.namespace, object)
+    }
+
+    /// Methods named with a `_` prefix or declared `private` are internal to their object.
+    pub fn is_private(&self) -> bool {
+        self.name.starts_with('_') || self.contract().is_some_and(Contract::private)
+    }
+
+    /// A command for `program` with the environment of the call.
+    pub fn command(&self, program: &PathBuf) -> Command {
+        let mut command = Command::new(program);
+
+        // Arguments of an outer call must not leak into a nested `irnix e`.
+        for (key, _) in env::vars_os() {
+            let key = key.to_string_lossy();
+            if key.starts_with("IRNIX_ARG_") || key.starts_with("IRNIX_FLAG_") || key == "IRNIX_MOCKS" || key == "IRNIX_LOCKS" {
+                command.env_remove(key.as_ref());
+            }
+        }
+        command.envs(self.environment());
+
+        command
+    }
+
+    /// A failing after hook can't undo the call, so it is only reported.
+    fn after(&self, exit_code: i32) {
+        for hook in &self.after_hooks {
+            match hooks::run(hook, self, Some(exit_code)) {
+                Ok(status) if status.success() => {}
+                Ok(status) => eprintln!(
+                    "The hook {:?} failed with exit code {}",
+                    hook,
+                    supervisor::exit_code(status)
+                ),
+                Err(err) => eprintln!("The hook {:?} failed to start: {err}", hook),
+            }
+        }
+    }
+
+    /// The cache of a `@pure` method. Methods whose file can't be read are not cached.
+    fn cache(&self, stdin: &[u8]) -> Option<Cache> {
+        let contract = self.contract().filter(|x| x.pure())?;
+
+        Cache::new(
+            &cache_dir().join("results"),
+            &self.dotted_name,
+            &self.path,
+            self.parsed.as_ref(),
+            stdin,
+            contract.ttl(),
+        )
+        .ok()
+    }
+
     pub fn execute(self) {
-        let err = Command::new(self.path)
-            .args(self.args)
-            .stdin(Stdio::inherit())
+        let stdin = match self.stdin.stdio() {
+            Ok(stdin) => stdin,
+            Err(err) => Error::new(ErrorCode::Io, format!("Failed to open stdin: {err}"))
+                .method(self.dotted_name)
+                .exit(),
+        };
+
+        let mut command = match &self.remote {
+            Some(remote) => {
+                let mut environment = self.environment();
+                for (key, value) in &mut environment {
+                    if key == "IRNIX_NAMESPACE" {
+                        *value = remote.namespace.to_string_lossy().to_string();
+                    }
+                }
+
+                remote.command(
+                    &remote.path(&self.namespace, &self.path),
+                    &self.args,
+                    &environment,
+                )
+            }
+            None => {
+                let mut command = self.command(&self.path);
+                command.args(&self.args);
+                command
+            }
+        };
+        command
+            .stdin(stdin)
             .stdout(Stdio::inherit())
-            .exec();
+            .stderr(Stdio::inherit());
+
+        // irnix waits for ssh to check the exit code of a remote method.
+        let supervision = self
+            .supervision
+            .clone()
+            .or_else(|| self.remote.as_ref().map(|_| Supervision::default()));
+
+        if let Some(supervision) = &supervision {
+            let input = match &self.stdin {
+                Input::Buffer(buffer) => Some(buffer.clone()),
+                _ => None,
+            };
+
+            let cache = self.cache(input.as_deref().unwrap_or_default());
+            if let Some((exit_code, stdout)) = cache.as_ref().and_then(|x| x.lookup()) {
+                audit::finish(Some(exit_code), Some(Duration::ZERO));
+                learn::finish(exit_code);
+                let _ = io::stdout().write_all(&stdout).and_then(|_| io::stdout().flush());
+                self.after(exit_code);
+                exit(exit_code);
+            }
+
+            let sinks = cache.as_ref().and_then(|x| x.sink().ok());
+            match supervisor::supervise(command, supervision, input, sinks.into_iter().collect()) {
+                Ok(outcome) => {
+                    audit::finish(Some(outcome.exit_code), Some(outcome.duration));
+                    learn::finish(outcome.exit_code);
+
+                    let declared = self.contract().is_none_or(|x| {
+                        x.error_codes().contains(&(outcome.exit_code as u32))
+                    });
+
+                    if let Some(cache) = cache {
+                        // A cache must never fail the call, so a result that can't be stored is dropped.
+                        if !outcome.timed_out && (outcome.exit_code == 0 || declared) {
+                            let _ = cache.store(outcome.exit_code);
+                        } else {
+                            cache.discard();
+                        }
+                    }
+
+                    self.after(outcome.exit_code);
+
+                    if outcome.timed_out {
+                        Error::new(
+                            ErrorCode::Timeout,
+                            format!(
+                                "The method exceeded its timeout of {}s and was terminated",
+                                supervision.timeout.unwrap_or_default().as_secs()
+                            ),
+                        )
+                        .method(self.dotted_name.clone())
+                        .exit();
+                    }
+
+                    if let Some(remote) = &self.remote
+                        && outcome.exit_code != 0
+                        && !declared
+                    {
+                        let error = if outcome.exit_code == SSH_EXIT_CODE {
+                            Error::new(
+                                ErrorCode::Remote,
+                                format!("ssh to \"{}\" failed", remote.host),
+                            )
+                        } else {
+                            Error::new(
+                                ErrorCode::UndeclaredExitCode,
+                                format!(
+                                    "The method exited on \"{}\" with {}, which its contract does not declare",
+                                    remote.host, outcome.exit_code
+                                ),
+                            )
+                        };
+
+                        error
+                            .method(self.dotted_name.clone())
+                            .token(outcome.exit_code.to_string())
+                            .exit();
+                    }
+
+                    exit(outcome.exit_code);
+                }
+                Err(err) => Error::new(ErrorCode::Exec, format!("Spawn failed: {err}"))
+                    .method(self.dotted_name.clone())
+                    .exit(),
+            }
+        }
+
+        audit::finish(None, None);
+        let err = command.exec();
+
+        Error::new(ErrorCode::Exec, format!("Exec failed: {err}"))
+            .method(self.dotted_name)
+            .exit()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::argv;
+    use std::ffi::OsStr;
+
+    #[test]
+    fn environment() {
+        let argv = ["prod", "a.tar", "b.tar", "--tag=x", "--tag", "y", "-vv", "--force"].map(String::from);
+        let contract = "deploy: target! files...? --tag=* -v* --force? --quiet?"
+            .parse::<Contract>()
+            .unwrap();
+
+        let mut method = Method::new("app#blue.deploy".into(), argv.to_vec(), "/ns".into()).unwrap();
+        method.parsed = Some(argv::parse(&argv, &contract).unwrap());
+        let environment = method.environment().into_iter().collect::<HashMap<_, _>>();
+        let get = |x: &str| environment.get(x).map(String::as_str);
+
+        assert_eq!(get("IRNIX_METHOD"), Some("app.deploy"));
+        assert_eq!(get("IRNIX_OBJECT"), Some("app"));
+        assert_eq!(get("IRNIX_INSTANCE"), Some("blue"));
+        assert_eq!(get("IRNIX_NAMESPACE"), Some("/ns"));
+        assert_eq!(get("IRNIX_ARG_target"), Some("prod"));
+        assert_eq!(get("IRNIX_ARG_files"), Some("a.tar\nb.tar"));
+        assert_eq!(get("IRNIX_FLAG_tag"), Some("x\ny"));
+        assert_eq!(get("IRNIX_FLAG_v"), Some("2"));
+        assert_eq!(get("IRNIX_FLAG_force"), Some("1"));
+        assert_eq!(get("IRNIX_FLAG_quiet"), None);
+    }
+
+    #[test]
+    fn inherited_environment() {
+        // Set as if by an outer call, the names are not used by any other test.
+        unsafe {
+            env::set_var("IRNIX_ARG_outer", "x");
+            env::set_var("IRNIX_FLAG_outer", "1");
+        }
+
+        let argv = ["prod".to_string()];
+        let contract = "deploy: target!".parse::<Contract>().unwrap();
+        let mut method = Method::new("app.deploy".into(), argv.to_vec(), "/ns".into()).unwrap();
+        method.parsed = Some(argv::parse(&argv, &contract).unwrap());
+
+        let command = method.command(&PathBuf::from("/bin/true"));
+        let environment = command.get_envs().collect::<HashMap<_, _>>();
+        let get = |x: &str| environment.get(OsStr::new(x)).copied();
 
-        eprintln!("Exec failed: {err}");
-        exit(1);
+        assert_eq!(get("IRNIX_ARG_outer"), Some(None));
+        assert_eq!(get("IRNIX_FLAG_outer"), Some(None));
+        assert_eq!(get("IRNIX_ARG_target"), Some(Some(OsStr::new("prod"))));
     }
 }
@@ -0,0 +1,145 @@
+use serde::{Deserialize, Serialize};
+use std::fs::{self, OpenOptions};
+use std::io::{self, ErrorKind, Write};
+use std::path::{self, Path, PathBuf};
+
+/// Methods replaced by scripts under `irnix test`, by name with `--mock` or by a directory
+/// laid out like the namespace whose methods shadow the ones of the namespace.
+///
+/// The runner saves them to a file of the run and passes it to `irnix e` with `--mocks`.
+/// Calls are recorded next to it, see [`log`].
+#[derive(Serialize, Deserialize, Default, Clone, Debug, PartialEq)]
+pub struct Mocks {
+    pub scripts: Vec<(String, PathBuf)>,
+    pub namespace: Option<PathBuf>,
+}
+
+impl Mocks {
+    pub fn load(file: &Path) -> io::Result<Mocks> {
+        Ok(serde_json::from_slice(&fs::read(file)?)?)
+    }
+
+    pub fn save(&self, file: &Path) -> io::Result<()> {
+        fs::write(file, serde_json::to_vec(self)?)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.scripts.is_empty() && self.namespace.is_none()
+    }
+
+    /// The script that replaces the dotted method, a script given by name wins over the overlay.
+    pub fn find(&self, method: &str) -> Option<PathBuf> {
+        if let Some((_, script)) = self.scripts.iter().find(|(name, _)| name == method) {
+            return Some(script.clone());
+        }
+
+        self.namespace
+            .as_ref()
+            .map(|x| x.join(method.replace('.', "/")))
+            .filter(|x| x.is_file())
+    }
+}
+
+/// Parses `object.method=script`. The script is made absolute, calls run in another directory.
+pub fn parse(value: &str) -> Result<(String, PathBuf), String> {
+    let (method, script) = value
+        .split_once('=')
+        .filter(|(method, script)| method.contains('.') && !script.is_empty())
+        .ok_or_else(|| format!("Expected `object.method=script`, got {value:?}"))?;
+
+    let script = PathBuf::from(script);
+    Ok((method.to_string(), path::absolute(&script).unwrap_or(script)))
+}
+
+/// The file the calls of mocked methods are recorded to, next to the mocks `file`.
+pub fn log(file: &Path) -> PathBuf {
+    file.with_file_name("calls")
+}
+
+/// A call of a mocked method, one JSON object per line of the [`log`].
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct Call {
+    pub method: String,
+    #[serde(default)]
+    pub args: Vec<String>,
+    /// `None` if the call had no stdin. An expected call without it matches any stdin.
+    pub stdin: Option<String>,
+}
+
+impl Call {
+    /// Whether a recorded call matches this expected one.
+    pub fn matches(&self, recorded: &Call) -> bool {
+        self.method == recorded.method
+            && self.args == recorded.args
+            && self.stdin.as_ref().is_none_or(|x| Some(x) == recorded.stdin.as_ref())
+    }
+}
+
+/// Appends the call to `log`.
+pub fn record(call: &Call, log: &Path) -> io::Result<()> {
+    let mut line = serde_json::to_vec(call)?;
+    line.push(b'\n');
+    // One write per line, so that concurrent calls do not interleave.
+    OpenOptions::new().create(true).append(true).open(log)?.write_all(&line)
+}
+
+/// The calls recorded to `log`, in order.
+pub fn calls(log: &Path) -> io::Result<Vec<Call>> {
+    let text = match fs::read_to_string(log) {
+        Ok(text) => text,
+        Err(err) if err.kind() == ErrorKind::NotFound => return Ok(vec![]),
+        Err(err) => return Err(err),
+    };
+
+    text.lines()
+        .map(|x| serde_json::from_str(x).map_err(io::Error::from))
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::temp_dir::TempDir;
+
+    #[test]
+    fn mocks() {
+        let root = TempDir::new("mock");
+        fs::create_dir_all(root.join("net/wifi")).unwrap();
+        fs::write(root.join("net/wifi/scan"), "").unwrap();
+
+        let (method, script) = parse("net.wifi.connect=fixtures/connect.sh").unwrap();
+        assert_eq!(method, "net.wifi.connect");
+        assert!(script.is_absolute() && script.ends_with("fixtures/connect.sh"));
+        assert!(parse("connect=fixtures/connect.sh").is_err());
+        assert!(parse("net.wifi.connect=").is_err());
+
+        let mocks = Mocks {
+            scripts: vec![(method, script.clone())],
+            namespace: Some(root.to_path_buf()),
+        };
+        assert_eq!(mocks.find("net.wifi.connect"), Some(script));
+        assert_eq!(mocks.find("net.wifi.scan"), Some(root.join("net/wifi/scan")));
+        assert_eq!(mocks.find("net.wifi.status"), None);
+        assert!(Mocks::default().is_empty());
+
+        mocks.save(&root.join("mocks")).unwrap();
+        assert_eq!(Mocks::load(&root.join("mocks")).unwrap(), mocks);
+        assert_eq!(log(&root.join("mocks")), root.join("calls"));
+    }
+
+    #[test]
+    fn matching() {
+        let recorded = Call {
+            method: "net.wifi.connect".into(),
+            args: vec!["home".into()],
+            stdin: Some("secret\n".into()),
+        };
+        let expected = serde_json::from_str::<Call>(r#"{"method": "net.wifi.connect", "args": ["home"]}"#).unwrap();
+
+        assert!(expected.matches(&recorded));
+        assert!(recorded.matches(&recorded));
+        assert!(!Call { stdin: Some("other\n".into()), ..recorded.clone() }.matches(&recorded));
+        assert!(!Call { args: vec![], ..recorded.clone() }.matches(&recorded));
+    }
+}
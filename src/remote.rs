@@ -0,0 +1,141 @@
+use std::io;
+use std::path::{self, Path, PathBuf};
+use std::process::{Command, Stdio};
+
+/// Exit code of `ssh` itself when the connection fails.
+pub const SSH_EXIT_CODE: i32 = 255;
+
+/// A host that runs the methods of a call, with the namespace it keeps in sync.
+pub struct Remote {
+    pub(crate) host: String,
+    pub(crate) namespace: PathBuf,
+    /// `ssh`, or the program in `IRNIX_SSH`, for example a shim in tests.
+    pub(crate) ssh: PathBuf,
+}
+
+/// Splits `host:net.wifi.status` into the host and the method.
+pub fn split(method: &str) -> (Option<&str>, &str) {
+    match method.split_once(':') {
+        Some((host, method)) => (Some(host), method),
+        None => (None, method),
+    }
+}
+
+impl Remote {
+    /// The remote counterpart of a local path in `local` namespace. Paths outside
+    /// of it, such as the target of an interface, are expected at the same place.
+    pub fn path(&self, local: &Path, path: &Path) -> PathBuf {
+        let absolute = |x: &Path| path::absolute(x).unwrap_or(x.to_path_buf());
+        let path = absolute(path);
+
+        [absolute(local), local.canonicalize().unwrap_or_default()]
+            .iter()
+            .find_map(|x| path.strip_prefix(x).ok())
+            .map(|x| self.namespace.join(x))
+            .unwrap_or(path)
+    }
+
+    /// A command that runs `program` on the host with `args` and `environment`.
+    /// Stdin, stdout and stderr are streamed by `ssh`.
+    pub fn command(
+        &self,
+        program: &Path,
+        args: &[String],
+        environment: &[(String, String)],
+    ) -> Command {
+        let mut remote = vec!["exec".to_string(), "env".to_string()];
+        remote.extend(environment.iter().map(|(k, v)| quote(&format!("{k}={v}"))));
+        remote.push(quote(&program.to_string_lossy()));
+        remote.extend(args.iter().map(|x| quote(x)));
+
+        let mut command = Command::new(&self.ssh);
+        command.arg(&self.host).arg(remote.join(" "));
+        command
+    }
+
+    /// Reads a file on the host, `None` if it does not exist.
+    pub fn read(&self, path: &Path) -> io::Result<Option<String>> {
+        let output = Command::new(&self.ssh)
+            .arg(&self.host)
+            .arg(format!(
+                "test -f {path} && cat {path} || exit 3",
+                path = quote(&path.to_string_lossy())
+            ))
+            .stdin(Stdio::null())
+            .stderr(Stdio::inherit())
+            .output()?;
+
+        match output.status.code() {
+            Some(0) => Ok(Some(String::from_utf8_lossy(&output.stdout).to_string())),
+            Some(3) => Ok(None),
+            _ => Err(io::Error::other(format!(
+                "ssh {} failed with {}",
+                self.host, output.status
+            ))),
+        }
+    }
+}
+
+/// Quotes `value` for a POSIX shell.
+fn quote(value: &str) -> String {
+    format!("'{}'", value.replace('\'', r"'\''"))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::temp_dir::TempDir;
+    use std::os::unix::fs::PermissionsExt;
+    use std::fs;
+
+    #[test]
+    fn split_host() {
+        assert_eq!(split("router:net.wifi.status"), (Some("router"), "net.wifi.status"));
+        assert_eq!(split("net.wifi.status"), (None, "net.wifi.status"));
+    }
+
+    #[test]
+    fn quoting() {
+        assert_eq!(quote("a b"), "'a b'");
+        assert_eq!(quote("it's"), r"'it'\''s'");
+    }
+
+    #[test]
+    fn shim() {
+        let dir = TempDir::new("remote");
+        fs::create_dir_all(dir.join("ns/net")).unwrap();
+        fs::write(dir.join("ns/net/.self"), "status: stdout?\n").unwrap();
+
+        // Drops the host and runs the command locally, as ssh would on the host.
+        let ssh = dir.join("ssh");
+        fs::write(&ssh, "#!/bin/sh\nshift\nexec sh -c \"$1\"\n").unwrap();
+        fs::set_permissions(&ssh, fs::Permissions::from_mode(0o755)).unwrap();
+
+        let remote = Remote {
+            host: "router".into(),
+            namespace: dir.join("ns"),
+            ssh,
+        };
+
+        let local = Path::new("/srv/irnix");
+        assert_eq!(
+            remote.path(local, &local.join("net/.self")),
+            dir.join("ns/net/.self")
+        );
+        assert_eq!(
+            remote.read(&remote.path(local, &local.join("net/.self"))).unwrap(),
+            Some("status: stdout?\n".into())
+        );
+        assert_eq!(remote.read(&dir.join("ns/wifi/.self")).unwrap(), None);
+
+        let output = remote
+            .command(
+                Path::new("printf"),
+                &["%s|%s".into(), "it's".into(), "$IRNIX_METHOD".into()],
+                &[("IRNIX_METHOD".into(), "net.status".into())],
+            )
+            .output()
+            .unwrap();
+        assert_eq!(String::from_utf8_lossy(&output.stdout), "it's|$IRNIX_METHOD");
+    }
+}
@@ -0,0 +1,161 @@
+use crate::method::Method;
+use regex::Regex;
+use std::fs::{self, File, OpenOptions};
+use std::io;
+use std::os::fd::{AsFd, AsRawFd};
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::{Path, PathBuf};
+use std::process::{ExitStatus, Stdio};
+use std::sync::LazyLock;
+
+static OBJECT: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"^[\w-]+(\.[\w-]+)*(#[\w-]+)?$").unwrap());
+
+/// Whether `object` is a dotted object with an optional instance, and so stays inside the root.
+pub fn is_valid(object: &str) -> bool {
+    OBJECT.is_match(object)
+}
+
+/// The state directory of an object, `root/<dotted object>[#instance]`.
+pub fn path(root: &Path, object: &str) -> PathBuf {
+    root.join(object)
+}
+
+/// Creates the state directory, returns whether it did not exist yet.
+pub fn create(path: &Path) -> io::Result<bool> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+
+    match fs::create_dir(path) {
+        Ok(()) => Ok(true),
+        Err(err) if err.kind() == io::ErrorKind::AlreadyExists => Ok(false),
+        Err(err) => Err(err),
+    }
+}
+
+/// Takes an exclusive lock on the initialisation of `object`, so that concurrent first calls
+/// wait for its `.init` instead of seeing a half initialised state. The descriptor is closed
+/// on exec, the method does not inherit it.
+pub fn lock(root: &Path, object: &str) -> io::Result<File> {
+    let dir = root.join(".locks");
+    fs::create_dir_all(&dir)?;
+
+    let file = OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .custom_flags(libc::O_NOFOLLOW)
+        .mode(0o600)
+        .open(dir.join(object))?;
+
+    loop {
+        if unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX) } == 0 {
+            return Ok(file);
+        }
+
+        let err = io::Error::last_os_error();
+        if err.kind() != io::ErrorKind::Interrupted {
+            return Err(err);
+        }
+    }
+}
+
+/// Runs the `.init` of an object with the environment of the call. Its output goes
+/// to stderr to keep stdout to the method.
+pub fn init(path: &PathBuf, method: &Method) -> io::Result<ExitStatus> {
+    method
+        .command(path)
+        .stdin(Stdio::null())
+        .stdout(Stdio::from(io::stderr().as_fd().try_clone_to_owned()?))
+        .status()
+}
+
+/// The state directories of `object` and of every instance of it. An `object#instance`
+/// matches only itself.
+pub fn find(root: &Path, object: &str) -> io::Result<Vec<PathBuf>> {
+    let entries = match fs::read_dir(root) {
+        Ok(entries) => entries,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(vec![]),
+        Err(err) => return Err(err),
+    };
+
+    let mut found = Vec::new();
+    for entry in entries {
+        let entry = entry?;
+        let name = entry.file_name().to_string_lossy().to_string();
+
+        if name == object || (!object.contains('#') && name.starts_with(&format!("{object}#"))) {
+            found.push(entry.path());
+        }
+    }
+
+    found.sort();
+    Ok(found)
+}
+
+/// Paths of the files in a state directory, relative to it.
+pub fn files(dir: &Path) -> io::Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    let mut dirs = vec![dir.to_path_buf()];
+
+    while let Some(current) = dirs.pop() {
+        for entry in fs::read_dir(&current)? {
+            let path = entry?.path();
+            if path.is_dir() {
+                dirs.push(path);
+            } else if let Ok(relative) = path.strip_prefix(dir) {
+                files.push(relative.to_path_buf());
+            }
+        }
+    }
+
+    files.sort();
+    Ok(files)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::temp_dir::TempDir;
+
+    #[test]
+    fn instances() {
+        let root = TempDir::new("state");
+
+        assert!(is_valid("net.wifi#home"));
+        assert!(!is_valid("../counter"));
+        assert!(!is_valid("counter#"));
+
+        assert!(create(&path(&root, "counter")).unwrap());
+        assert!(!create(&path(&root, "counter")).unwrap());
+        assert!(create(&path(&root, "counter#a")).unwrap());
+        assert!(create(&path(&root, "counters")).unwrap());
+        fs::create_dir_all(root.join("counter#a/log")).unwrap();
+        fs::write(root.join("counter#a/value"), "1").unwrap();
+        fs::write(root.join("counter#a/log/1"), "").unwrap();
+
+        assert_eq!(
+            find(&root, "counter").unwrap(),
+            vec![root.join("counter"), root.join("counter#a")]
+        );
+        assert_eq!(find(&root, "counter#a").unwrap(), vec![root.join("counter#a")]);
+        assert_eq!(
+            files(&root.join("counter#a")).unwrap(),
+            vec![PathBuf::from("log/1"), PathBuf::from("value")]
+        );
+    }
+
+    #[test]
+    fn init_lock() {
+        let root = TempDir::new("state-lock");
+
+        let lock = lock(&root, "counter#a").unwrap();
+        let other = File::open(root.join(".locks/counter#a")).unwrap();
+        assert_ne!(unsafe { libc::flock(other.as_raw_fd(), libc::LOCK_EX | libc::LOCK_NB) }, 0);
+
+        drop(lock);
+        assert_eq!(unsafe { libc::flock(other.as_raw_fd(), libc::LOCK_EX | libc::LOCK_NB) }, 0);
+        assert_eq!(find(&root, "counter").unwrap(), Vec::<PathBuf>::new());
+    }
+}
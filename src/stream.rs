@@ -0,0 +1,256 @@
+use libc::c_int;
+use std::io::{IsTerminal, Read};
+use std::os::fd::BorrowedFd;
+use std::os::unix::fs::MetadataExt;
+use std::path::PathBuf;
+use std::process::Stdio;
+use std::str::FromStr;
+use std::{fs, io, mem};
+
+/// What a standard stream of irnix is connected to.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Stream {
+    Tty,
+    Pipe,
+    File,
+    Socket,
+    Null,
+    Closed,
+}
+
+impl Stream {
+    pub fn stdin() -> Stream {
+        Stream::of(libc::STDIN_FILENO)
+    }
+
+    pub fn stdout() -> Stream {
+        Stream::of(libc::STDOUT_FILENO)
+    }
+
+    /// Detects the type of `fd` with `fstat`. Character devices other than a terminal
+    /// and `/dev/null` are treated as files.
+    pub fn of(fd: c_int) -> Stream {
+        let mut stat: libc::stat = unsafe { mem::zeroed() };
+        if unsafe { libc::fstat(fd, &mut stat) } != 0 {
+            return Stream::Closed;
+        }
+
+        if unsafe { BorrowedFd::borrow_raw(fd) }.is_terminal() {
+            return Stream::Tty;
+        }
+
+        match stat.st_mode & libc::S_IFMT {
+            libc::S_IFIFO => Stream::Pipe,
+            libc::S_IFSOCK => Stream::Socket,
+            libc::S_IFCHR if fs::metadata("/dev/null").is_ok_and(|x| x.rdev() == stat.st_rdev) => {
+                Stream::Null
+            }
+            _ => Stream::File,
+        }
+    }
+
+    /// The stream of a contract mode such as `stdin:pipe!`.
+    pub fn from_mode(mode: &str) -> Option<Stream> {
+        match mode {
+            "pipe" => Some(Stream::Pipe),
+            "file" => Some(Stream::File),
+            "tty" => Some(Stream::Tty),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Stream::Tty => "a terminal",
+            Stream::Pipe => "a pipe",
+            Stream::File => "a file",
+            Stream::Socket => "a socket",
+            Stream::Null => "/dev/null",
+            Stream::Closed => "closed",
+        }
+    }
+
+    /// Whether the stream carries data. A terminal, `/dev/null` and a closed stream do not.
+    pub fn is_provided(&self) -> bool {
+        matches!(self, Stream::Pipe | Stream::File | Stream::Socket)
+    }
+
+    /// Whether another process reads or writes the other end of the stream. A socket is not,
+    /// under systemd or inetd it is where the output of a service goes.
+    pub fn is_pipeline(&self) -> bool {
+        matches!(self, Stream::Pipe)
+    }
+}
+
+/// Where the stdin of a method comes from.
+pub enum Input {
+    /// The stdin of irnix, passed on as is.
+    Inherit(Stream),
+    /// A file given with `--stdin-file`, for example a recorded stdin.
+    File(PathBuf),
+    /// Stdin read ahead of the call. Only written to a supervised child.
+    Buffer(Vec<u8>),
+    /// The stdin of irnix is closed, the method gets `/dev/null`.
+    None,
+}
+
+impl Input {
+    pub fn stdin() -> Input {
+        match Stream::stdin() {
+            Stream::Closed => Input::None,
+            stream => Input::Inherit(stream),
+        }
+    }
+
+    /// The stream the method will see, used to check the contract.
+    pub fn stream(&self) -> Stream {
+        match self {
+            Input::Inherit(stream) => *stream,
+            Input::File(_) => Stream::File,
+            Input::Buffer(_) => Stream::Pipe,
+            Input::None => Stream::Closed,
+        }
+    }
+
+    /// Reads the whole input. The stdin of irnix can only be read once.
+    pub fn read(&self) -> io::Result<Vec<u8>> {
+        match self {
+            Input::Inherit(_) => {
+                let mut buffer = Vec::new();
+                io::stdin().lock().read_to_end(&mut buffer)?;
+                Ok(buffer)
+            }
+            Input::File(path) => fs::read(path),
+            Input::Buffer(buffer) => Ok(buffer.clone()),
+            Input::None => Ok(Vec::new()),
+        }
+    }
+
+    pub fn stdio(&self) -> io::Result<Stdio> {
+        match self {
+            Input::Inherit(_) => Ok(Stdio::inherit()),
+            Input::File(path) => fs::File::open(path).map(Stdio::from),
+            Input::Buffer(_) => Ok(Stdio::piped()),
+            Input::None => Ok(Stdio::null()),
+        }
+    }
+}
+
+/// The format of stdin declared with `@stdin_format`.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Format {
+    /// Valid UTF-8.
+    Text,
+    /// A single JSON value.
+    Json,
+    /// A JSON value on every non-empty line.
+    Jsonl,
+}
+
+impl Format {
+    pub fn validate(&self, input: &[u8]) -> Result<(), String> {
+        let text = str::from_utf8(input).map_err(|err| format!("stdin is not UTF-8: {err}"))?;
+
+        match self {
+            Format::Text => Ok(()),
+            Format::Json => serde_json::from_str::<serde_json::Value>(text)
+                .map(|_| ())
+                .map_err(|err| format!("stdin is not JSON: {err}")),
+            Format::Jsonl => {
+                for (number, line) in text.lines().enumerate() {
+                    if line.trim().is_empty() {
+                        continue;
+                    }
+
+                    serde_json::from_str::<serde_json::Value>(line)
+                        .map_err(|err| format!("Line {} of stdin is not JSON: {err}", number + 1))?;
+                }
+
+                Ok(())
+            }
+        }
+    }
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(Format::Text),
+            "json" => Ok(Format::Json),
+            "jsonl" => Ok(Format::Jsonl),
+            _ => Err(format!("Unknown stdin format: {s}")),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::fs::File;
+    use std::os::fd::AsRawFd;
+    use std::os::unix::net::UnixStream;
+
+    #[test]
+    fn detect() {
+        let mut fds = [0; 2];
+        assert_eq!(unsafe { libc::pipe(fds.as_mut_ptr()) }, 0);
+        assert_eq!(Stream::of(fds[0]), Stream::Pipe);
+        assert_eq!(Stream::of(fds[1]), Stream::Pipe);
+        unsafe {
+            libc::close(fds[0]);
+            libc::close(fds[1]);
+        }
+
+        let null = File::open("/dev/null").unwrap();
+        assert_eq!(Stream::of(null.as_raw_fd()), Stream::Null);
+
+        let file = File::open("Cargo.toml").unwrap();
+        assert_eq!(Stream::of(file.as_raw_fd()), Stream::File);
+
+        assert_eq!(Stream::of(-1), Stream::Closed);
+    }
+
+    #[test]
+    fn socket() {
+        let (left, right) = UnixStream::pair().unwrap();
+        assert_eq!(Stream::of(left.as_raw_fd()), Stream::Socket);
+        assert_eq!(Stream::of(right.as_raw_fd()), Stream::Socket);
+        assert!(Stream::Socket.is_provided());
+        assert!(!Stream::Socket.is_pipeline());
+    }
+
+    #[test]
+    fn provided() {
+        assert!(Stream::Pipe.is_provided());
+        assert!(Stream::File.is_provided());
+        assert!(!Stream::Null.is_provided());
+        assert!(!Stream::Tty.is_provided());
+        assert!(!Stream::File.is_pipeline());
+        assert_eq!(Stream::from_mode("pipe"), Some(Stream::Pipe));
+        assert_eq!(Stream::from_mode("fifo"), None);
+    }
+
+    #[test]
+    fn format() {
+        assert!(Format::Json.validate(br#"{"ssid": "home"}"#).is_ok());
+        assert!(Format::Json.validate(b"{").is_err());
+        assert!(Format::Jsonl.validate(b"1\n\n{\"a\": 2}\n").is_ok());
+        assert_eq!(
+            Format::Jsonl.validate(b"1\nx\n").unwrap_err(),
+            "Line 2 of stdin is not JSON: expected value at line 1 column 1"
+        );
+        assert!(Format::Text.validate(&[0xff]).is_err());
+        assert_eq!("jsonl".parse::<Format>(), Ok(Format::Jsonl));
+        assert!("yaml".parse::<Format>().is_err());
+    }
+
+    #[test]
+    fn input() {
+        assert_eq!(Input::File("x".into()).stream(), Stream::File);
+        assert_eq!(Input::Buffer(vec![]).stream(), Stream::Pipe);
+        assert_eq!(Input::Buffer(b"abc".to_vec()).read().unwrap(), b"abc");
+        assert!(Input::None.read().unwrap().is_empty());
+    }
+}
@@ -0,0 +1,97 @@
+use crate::config::unwrap_strictness;
+use crate::index;
+use clap::ValueEnum;
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+
+/// What happens when a method without a contract is called.
+#[derive(ValueEnum, Clone, Copy, PartialEq, Debug, Default)]
+pub enum Strictness {
+    /// Run it, as if every call matched.
+    #[default]
+    Off,
+    /// Run it after a notice on stderr.
+    Warn,
+    /// Refuse to run it.
+    Strict,
+}
+
+impl Strictness {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Strictness::Off => "off",
+            Strictness::Warn => "warn",
+            Strictness::Strict => "strict",
+        }
+    }
+}
+
+impl FromStr for Strictness {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        <Strictness as ValueEnum>::from_str(s, true)
+    }
+}
+
+/// Whether a `.self` line is a directive such as `@strict warn` rather than a contract.
+pub fn is_directive(line: &str) -> bool {
+    line.split_whitespace().next() == Some("@strict")
+}
+
+/// The policy set by the last `@strict` directive of a `.self` file.
+pub fn directive(contracts: &str) -> Option<Strictness> {
+    contracts
+        .lines()
+        .filter(|x| is_directive(x))
+        .filter_map(|x| x.split_whitespace().nth(1)?.parse().ok())
+        .next_back()
+}
+
+/// The policy of an object. The nearest `.self` with a directive wins, from the object
+/// up to the namespace itself, then `IRNIX_STRICT`.
+pub fn resolve(namespace: &Path, object: &str) -> Strictness {
+    let parts = object.split('.').collect::<Vec<_>>();
+
+    (0..=parts.len())
+        .rev()
+        .map(|x| namespace.join(parts[..x].join("/")))
+        .find_map(|dir| {
+            let contracts = match index::current().and_then(|x| x.object(&dir).cloned()) {
+                Some(object) => object.contracts,
+                None => fs::read_to_string(dir.join(".self")).ok(),
+            };
+            directive(&contracts?)
+        })
+        .unwrap_or_else(|| unwrap_strictness(None))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::temp_dir::TempDir;
+
+    #[test]
+    fn directives() {
+        assert!(is_directive("@strict warn"));
+        assert!(!is_directive("status: stdout? @stdin_format json"));
+        assert_eq!(directive("status: stdout?\n@strict warn\n"), Some(Strictness::Warn));
+        assert_eq!(directive("@strict warn\n@strict strict"), Some(Strictness::Strict));
+        assert_eq!(directive("@strict sometimes"), None);
+        assert_eq!(directive("status: stdout?"), None);
+    }
+
+    #[test]
+    fn inheritance() {
+        let namespace = TempDir::new("strictness");
+        fs::create_dir_all(namespace.join("net/wifi")).unwrap();
+        fs::create_dir_all(namespace.join("sys")).unwrap();
+        fs::write(namespace.join(".self"), "@strict warn\n").unwrap();
+        fs::write(namespace.join("net/.self"), "@strict strict\n").unwrap();
+        fs::write(namespace.join("net/wifi/.self"), "status: stdout?\n").unwrap();
+
+        assert_eq!(resolve(&namespace, "net.wifi"), Strictness::Strict);
+        assert_eq!(resolve(&namespace, "sys"), Strictness::Warn);
+    }
+}
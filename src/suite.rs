@@ -0,0 +1,272 @@
+use crate::error::{Error, ErrorCode};
+use crate::mock::Call;
+use regex::Regex;
+use serde::Deserialize;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+
+/// An example call of a method, one JSON object per line of an object's `.tests` file,
+/// or of any file in its `.tests` directory.
+///
+/// ```json
+/// {"method": "status", "args": ["wlan0"], "stdout_regex": "^up", "exit_code": 0}
+/// ```
+#[derive(Deserialize, Debug, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct Case {
+    /// Name of the method within the object.
+    pub method: String,
+    pub name: Option<String>,
+    #[serde(default)]
+    pub args: Vec<String>,
+    /// Text written to stdin. Without it or `stdin_file`, stdin is `/dev/null`.
+    pub stdin: Option<String>,
+    pub stdin_file: Option<PathBuf>,
+    /// The exact expected stdout.
+    pub stdout: Option<String>,
+    pub stdout_regex: Option<String>,
+    /// A golden file with the expected stdout.
+    pub stdout_file: Option<PathBuf>,
+    #[serde(default)]
+    pub exit_code: i32,
+    /// The irnix error the call is expected to be refused with, such as `E_MISSING_ARGUMENTS`.
+    pub error: Option<String>,
+    /// The calls of mocked methods the case is expected to make, in order.
+    pub calls: Option<Vec<Call>>,
+    /// Directory that `stdin_file` and `stdout_file` are relative to.
+    #[serde(skip)]
+    pub dir: PathBuf,
+}
+
+impl Case {
+    pub fn title(&self) -> String {
+        match &self.name {
+            Some(name) => name.clone(),
+            None => [self.method.clone()]
+                .into_iter()
+                .chain(self.args.iter().cloned())
+                .collect::<Vec<_>>()
+                .join(" "),
+        }
+    }
+
+    /// Compares the stdout of a run with every expectation of the case.
+    pub fn check_stdout(&self, stdout: &[u8]) -> Result<(), String> {
+        let stdout = String::from_utf8_lossy(stdout);
+
+        if let Some(expected) = &self.stdout
+            && *expected != stdout
+        {
+            return Err(format!("stdout was {stdout:?}, expected {expected:?}"));
+        }
+
+        if let Some(pattern) = &self.stdout_regex {
+            let regex = Regex::new(pattern).map_err(|err| format!("invalid stdout_regex: {err}"))?;
+            if !regex.is_match(&stdout) {
+                return Err(format!("stdout {stdout:?} does not match {pattern:?}"));
+            }
+        }
+
+        if let Some(path) = &self.stdout_file {
+            let path = self.dir.join(path);
+            let expected = fs::read_to_string(&path).map_err(|err| format!("{path:?}: {err}"))?;
+            if expected != stdout {
+                return Err(format!("stdout was {stdout:?}, expected the contents of {path:?}"));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Compares the calls recorded from mocked methods with the expected ones.
+    pub fn check_calls(&self, calls: &[Call]) -> Result<(), String> {
+        let Some(expected) = &self.calls else {
+            return Ok(());
+        };
+
+        match expected.len() == calls.len() && expected.iter().zip(calls).all(|(x, y)| x.matches(y)) {
+            true => Ok(()),
+            false => Err(format!(
+                "mocked calls were {}, expected {}",
+                serde_json::json!(calls),
+                serde_json::json!(expected)
+            )),
+        }
+    }
+}
+
+/// The cases of an object, from its `.tests` file or the files of its `.tests` directory.
+pub fn load(object: &Path) -> Result<Vec<Case>, Error> {
+    let path = object.join(".tests");
+
+    let files = match fs::read_dir(&path) {
+        Ok(entries) => {
+            let mut files = entries
+                .map(|x| x.map(|x| x.path()))
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(|err| Error::io(&path, err))?;
+            files.sort();
+            files
+        }
+        Err(err) if err.kind() == ErrorKind::NotFound => return Ok(vec![]),
+        Err(_) => vec![path.clone()],
+    };
+
+    let mut cases = Vec::new();
+    for file in files {
+        let text = fs::read_to_string(&file).map_err(|err| Error::io(&file, err))?;
+        let dir = file.parent().unwrap_or(object);
+        cases.extend(parse(&text, dir).map_err(|err| err.token(file.to_string_lossy()))?);
+    }
+
+    Ok(cases)
+}
+
+fn parse(text: &str, dir: &Path) -> Result<Vec<Case>, Error> {
+    let mut cases = Vec::new();
+
+    for (number, line) in text.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let mut case = serde_json::from_str::<Case>(line).map_err(|err| {
+            Error::new(ErrorCode::TestFile, format!("Invalid test on line {}: {err}", number + 1))
+        })?;
+        case.dir = dir.to_path_buf();
+        cases.push(case);
+    }
+
+    Ok(cases)
+}
+
+/// The result of one case, `failure` explains why it did not pass.
+pub struct Outcome {
+    pub method: String,
+    pub title: String,
+    pub failure: Option<String>,
+}
+
+/// Test Anything Protocol, version 13.
+pub fn tap(outcomes: &[Outcome]) -> String {
+    let mut report = format!("TAP version 13\n1..{}\n", outcomes.len());
+
+    for (number, outcome) in outcomes.iter().enumerate() {
+        let status = if outcome.failure.is_some() { "not ok" } else { "ok" };
+        report += &format!("{status} {} - {}: {}\n", number + 1, outcome.method, outcome.title);
+
+        if let Some(failure) = &outcome.failure {
+            report += &format!("  ---\n  message: {}\n  ...\n", serde_json::json!(failure));
+        }
+    }
+
+    report
+}
+
+/// JUnit XML, one test case per case with the object as its class.
+pub fn junit(outcomes: &[Outcome]) -> String {
+    let failures = outcomes.iter().filter(|x| x.failure.is_some()).count();
+    let mut report = format!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<testsuites>\n  <testsuite name=\"irnix\" tests=\"{}\" failures=\"{failures}\">\n",
+        outcomes.len()
+    );
+
+    for outcome in outcomes {
+        let (class, method) = outcome.method.rsplit_once('.').unwrap_or(("", &outcome.method));
+        let attributes = format!(
+            "classname=\"{}\" name=\"{}\"",
+            escape(class),
+            escape(&format!("{method}: {}", outcome.title))
+        );
+
+        report += &match &outcome.failure {
+            Some(failure) => format!(
+                "    <testcase {attributes}>\n      <failure message=\"{}\"/>\n    </testcase>\n",
+                escape(failure)
+            ),
+            None => format!("    <testcase {attributes}/>\n"),
+        };
+    }
+
+    report + "  </testsuite>\n</testsuites>\n"
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\n', "&#10;")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn cases() {
+        let cases = parse(
+            "{\"method\": \"status\", \"args\": [\"wlan0\"], \"stdout_regex\": \"^up\"}\n\n{\"method\": \"scan\", \"exit_code\": 3}\n",
+            Path::new("/ns/net"),
+        )
+        .unwrap();
+
+        assert_eq!(cases.len(), 2);
+        assert_eq!(cases[0].title(), "status wlan0");
+        assert_eq!(cases[0].dir, PathBuf::from("/ns/net"));
+        assert_eq!(cases[1].exit_code, 3);
+
+        assert!(cases[0].check_stdout(b"up 54Mb/s\n").is_ok());
+        assert!(cases[0].check_stdout(b"down\n").is_err());
+        assert!(cases[1].check_stdout(b"anything").is_ok());
+
+        assert!(parse("{\"method\": \"status\", \"stdot\": \"\"}", Path::new("/")).is_err());
+    }
+
+    #[test]
+    fn calls() {
+        let cases = parse("{\"method\": \"status\"}", Path::new("/")).unwrap();
+        assert!(cases[0].check_calls(&[]).is_ok());
+
+        let case = &parse(
+            "{\"method\": \"up\", \"calls\": [{\"method\": \"net.wifi.connect\", \"args\": [\"home\"]}]}",
+            Path::new("/"),
+        )
+        .unwrap()[0];
+        let call = Call {
+            method: "net.wifi.connect".into(),
+            args: vec!["home".into()],
+            stdin: None,
+        };
+        assert!(case.check_calls(std::slice::from_ref(&call)).is_ok());
+        assert!(case.check_calls(&[]).is_err());
+        assert!(case.check_calls(&[call.clone(), call]).is_err());
+    }
+
+    #[test]
+    fn reports() {
+        let outcomes = [
+            Outcome {
+                method: "net.wifi.status".into(),
+                title: "up".into(),
+                failure: None,
+            },
+            Outcome {
+                method: "net.wifi.scan".into(),
+                title: "<empty>".into(),
+                failure: Some("exited with 1, expected 0".into()),
+            },
+        ];
+
+        assert_eq!(
+            tap(&outcomes),
+            "TAP version 13\n1..2\nok 1 - net.wifi.status: up\nnot ok 2 - net.wifi.scan: <empty>\n  ---\n  message: \"exited with 1, expected 0\"\n  ...\n"
+        );
+
+        let junit = junit(&outcomes);
+        assert!(junit.contains("<testsuite name=\"irnix\" tests=\"2\" failures=\"1\">"));
+        assert!(junit.contains("<testcase classname=\"net.wifi\" name=\"status: up\"/>"));
+        assert!(junit.contains("name=\"scan: &lt;empty&gt;\">\n      <failure message=\"exited with 1, expected 0\"/>"));
+    }
+}
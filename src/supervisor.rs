@@ -0,0 +1,350 @@
+use libc::{SIGHUP, SIGINT, SIGQUIT, SIGTERM, SIGUSR1, SIGUSR2, c_int, c_void, siginfo_t};
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Write};
+use std::os::unix::process::{CommandExt, ExitStatusExt};
+use std::path::PathBuf;
+use std::process::{Child, ChildStdout, Command, ExitStatus, Stdio};
+use std::sync::atomic::{AtomicI32, Ordering};
+use std::time::{Duration, Instant};
+use std::{io, mem, ptr, thread};
+
+/// Exit code reported when a supervised method exceeds its wall-clock timeout.
+pub const TIMEOUT_EXIT_CODE: i32 = 124;
+
+const KILL_GRACE: Duration = Duration::from_secs(5);
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+const FORWARDED_SIGNALS: [c_int; 6] = [SIGHUP, SIGINT, SIGQUIT, SIGTERM, SIGUSR1, SIGUSR2];
+
+static CHILD: AtomicI32 = AtomicI32::new(0);
+
+/// Limits applied to a method that runs as a child of irnix instead of replacing it.
+#[derive(PartialEq, Debug, Clone, Default)]
+pub struct Supervision {
+    pub(crate) timeout: Option<Duration>,
+    pub(crate) cpu: Option<Duration>,
+    pub(crate) memory: Option<u64>,
+    pub(crate) files: Option<u64>,
+    pub(crate) cwd: Option<PathBuf>,
+    /// Stdout of the method is also appended to this file.
+    pub(crate) tee: Option<PathBuf>,
+    /// Stdin of the method is saved to this file to be replayed with `--stdin-file`.
+    pub(crate) record: Option<PathBuf>,
+}
+
+impl Supervision {
+    /// Takes every limit that is not set for this call from `declared`.
+    pub fn or(self, declared: &Supervision) -> Supervision {
+        Supervision {
+            timeout: self.timeout.or(declared.timeout),
+            cpu: self.cpu.or(declared.cpu),
+            memory: self.memory.or(declared.memory),
+            files: self.files.or(declared.files),
+            cwd: self.cwd.or_else(|| declared.cwd.clone()),
+            tee: self.tee.or_else(|| declared.tee.clone()),
+            record: self.record.or_else(|| declared.record.clone()),
+        }
+    }
+}
+
+pub struct Outcome {
+    pub exit_code: i32,
+    pub duration: Duration,
+    pub timed_out: bool,
+}
+
+/// Spawns `command` as a child, applies the limits and waits for it.
+///
+/// Signals sent to irnix by other processes are forwarded to the child.
+/// `input` is written to the stdin of the child if the command pipes it,
+/// stdout is copied to every file in `sinks` as well as to the stdout of irnix.
+pub fn supervise(
+    mut command: Command,
+    supervision: &Supervision,
+    input: Option<Vec<u8>>,
+    mut sinks: Vec<File>,
+) -> io::Result<Outcome> {
+    if let Some(cwd) = &supervision.cwd {
+        command.current_dir(cwd);
+    }
+
+    if let Some(path) = &supervision.tee {
+        sinks.push(OpenOptions::new().create(true).append(true).open(path)?);
+    }
+
+    if !sinks.is_empty() {
+        command.stdout(Stdio::piped());
+    }
+
+    let limits = [
+        (libc::RLIMIT_CPU, supervision.cpu.map(|x| x.as_secs().max(1))),
+        (libc::RLIMIT_AS, supervision.memory),
+        (libc::RLIMIT_NOFILE, supervision.files),
+    ];
+
+    // Its own process group, so that a timeout also reaches the processes it started.
+    command.process_group(0);
+    // The terminal is only handed over, and taken back, if irnix has it. Run in the
+    // background, it would take the terminal away from the job in the foreground.
+    let terminal = unsafe {
+        libc::isatty(libc::STDIN_FILENO) == 1 && libc::tcgetpgrp(libc::STDIN_FILENO) == libc::getpgrp()
+    };
+
+    unsafe {
+        command.pre_exec(move || {
+            #[cfg(target_os = "linux")]
+            libc::prctl(libc::PR_SET_PDEATHSIG, libc::SIGKILL);
+
+            // A group in the background can't read the terminal, so it is handed over.
+            if terminal {
+                foreground(libc::getpid());
+            }
+
+            for (resource, limit) in limits {
+                if let Some(limit) = limit {
+                    let limit = libc::rlimit {
+                        rlim_cur: limit as libc::rlim_t,
+                        rlim_max: limit as libc::rlim_t,
+                    };
+
+                    if libc::setrlimit(resource, &limit) != 0 {
+                        return Err(io::Error::last_os_error());
+                    }
+                }
+            }
+
+            Ok(())
+        });
+    }
+
+    forward_signals();
+
+    let start = Instant::now();
+    let mut child = command.spawn()?;
+    CHILD.store(child.id() as i32, Ordering::SeqCst);
+
+    if let Some(mut stdin) = child.stdin.take() {
+        let input = input.unwrap_or_default();
+        // The child may exit without reading everything, a broken pipe is not an error here.
+        thread::spawn(move || stdin.write_all(&input));
+    }
+
+    let copier = child
+        .stdout
+        .take()
+        .map(|stdout| thread::spawn(move || copy_tee(stdout, sinks)));
+
+    let result = wait(&mut child, supervision.timeout, start);
+    CHILD.store(0, Ordering::SeqCst);
+    if terminal {
+        foreground(unsafe { libc::getpgrp() });
+    }
+
+    // After a timeout the whole group is dead, so nothing holds the pipe open anymore.
+    if let Some(copier) = copier {
+        let _ = copier.join();
+    }
+
+    let duration = start.elapsed();
+    match result? {
+        Some(status) => Ok(Outcome {
+            exit_code: exit_code(status),
+            duration,
+            timed_out: false,
+        }),
+        None => Ok(Outcome {
+            exit_code: TIMEOUT_EXIT_CODE,
+            duration,
+            timed_out: true,
+        }),
+    }
+}
+
+/// Copies the stdout of the child to the stdout of irnix and to `sinks`.
+fn copy_tee(mut stdout: ChildStdout, mut sinks: Vec<File>) -> io::Result<()> {
+    let mut buffer = [0; 8192];
+    let mut out = io::stdout().lock();
+
+    loop {
+        let read = stdout.read(&mut buffer)?;
+        if read == 0 {
+            return Ok(());
+        }
+
+        for sink in &mut sinks {
+            sink.write_all(&buffer[..read])?;
+        }
+        // The reader of irnix may go away, the log is still written.
+        let _ = out.write_all(&buffer[..read]).and_then(|_| out.flush());
+    }
+}
+
+/// Returns `None` if the child had to be terminated because of the timeout.
+fn wait(
+    child: &mut Child,
+    timeout: Option<Duration>,
+    start: Instant,
+) -> io::Result<Option<ExitStatus>> {
+    let Some(timeout) = timeout else {
+        return child.wait().map(Some);
+    };
+
+    loop {
+        if let Some(status) = child.try_wait()? {
+            return Ok(Some(status));
+        }
+
+        if start.elapsed() >= timeout {
+            terminate(child)?;
+            return Ok(None);
+        }
+
+        thread::sleep(POLL_INTERVAL);
+    }
+}
+
+/// Terminates the process group of the child, killing whatever is left after the grace period.
+fn terminate(child: &mut Child) -> io::Result<()> {
+    let group = -(child.id() as i32);
+    unsafe {
+        libc::kill(group, SIGTERM);
+    }
+
+    let deadline = Instant::now() + KILL_GRACE;
+    while Instant::now() < deadline && child.try_wait()?.is_none() {
+        thread::sleep(POLL_INTERVAL);
+    }
+
+    unsafe {
+        libc::kill(group, libc::SIGKILL);
+    }
+    child.wait().map(|_| ())
+}
+
+/// Makes `group` the foreground process group of the terminal on stdin.
+fn foreground(group: libc::pid_t) {
+    unsafe {
+        // Only the foreground group may change it without being stopped.
+        let previous = libc::signal(libc::SIGTTOU, libc::SIG_IGN);
+        libc::tcsetpgrp(libc::STDIN_FILENO, group);
+        libc::signal(libc::SIGTTOU, previous);
+    }
+}
+
+/// Shell convention: a child killed by a signal is reported as `128 + signal`.
+pub fn exit_code(status: ExitStatus) -> i32 {
+    status
+        .code()
+        .unwrap_or_else(|| 128 + status.signal().unwrap_or_default())
+}
+
+fn forward_signals() {
+    for signal in FORWARDED_SIGNALS {
+        unsafe {
+            let mut action: libc::sigaction = mem::zeroed();
+            action.sa_sigaction = forward_signal as *const () as usize;
+            action.sa_flags = libc::SA_SIGINFO | libc::SA_RESTART;
+            libc::sigemptyset(&mut action.sa_mask);
+            libc::sigaction(signal, &action, ptr::null_mut());
+        }
+    }
+}
+
+extern "C" fn forward_signal(signal: c_int, _info: *mut siginfo_t, _context: *mut c_void) {
+    // The child has its own process group, so signals of the terminal are forwarded too.
+    let child = CHILD.load(Ordering::SeqCst);
+    if child > 0 {
+        unsafe {
+            libc::kill(-child, signal);
+        }
+    }
+}
+
+/// Parses `30`, `30s`, `5m`, `2h` or `1d`. A bare number is seconds.
+pub fn parse_duration(value: &str) -> Result<Duration, String> {
+    let value = value.trim();
+    let (number, multiplier) = match value.chars().last() {
+        Some('s') => (&value[..value.len() - 1], 1),
+        Some('m') => (&value[..value.len() - 1], 60),
+        Some('h') => (&value[..value.len() - 1], 60 * 60),
+        Some('d') => (&value[..value.len() - 1], 60 * 60 * 24),
+        _ => (value, 1),
+    };
+
+    number
+        .parse::<u64>()
+        .ok()
+        .and_then(|x| x.checked_mul(multiplier))
+        .map(Duration::from_secs)
+        .ok_or_else(|| format!("Invalid duration: {value}"))
+}
+
+/// Parses `512`, `64K`, `512M` or `1G`. A bare number is bytes.
+pub fn parse_size(value: &str) -> Result<u64, String> {
+    let value = value.trim();
+    let (number, multiplier) = match value.chars().last() {
+        Some('K') => (&value[..value.len() - 1], 1 << 10),
+        Some('M') => (&value[..value.len() - 1], 1 << 20),
+        Some('G') => (&value[..value.len() - 1], 1 << 30),
+        _ => (value, 1),
+    };
+
+    number
+        .parse::<u64>()
+        .ok()
+        .and_then(|x| x.checked_mul(multiplier))
+        .ok_or_else(|| format!("Invalid size: {value}"))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn duration() {
+        assert_eq!(parse_duration("30"), Ok(Duration::from_secs(30)));
+        assert_eq!(parse_duration("30s"), Ok(Duration::from_secs(30)));
+        assert_eq!(parse_duration("5m"), Ok(Duration::from_secs(300)));
+        assert_eq!(parse_duration("2h"), Ok(Duration::from_secs(7200)));
+        assert_eq!(parse_duration("1d"), Ok(Duration::from_secs(86400)));
+        assert!(parse_duration("m").is_err());
+        assert!(parse_duration("5x").is_err());
+    }
+
+    #[test]
+    fn size() {
+        assert_eq!(parse_size("512"), Ok(512));
+        assert_eq!(parse_size("64K"), Ok(64 * 1024));
+        assert_eq!(parse_size("512M"), Ok(512 * 1024 * 1024));
+        assert_eq!(parse_size("1G"), Ok(1024 * 1024 * 1024));
+        assert!(parse_size("1T").is_err());
+    }
+
+    #[test]
+    fn overflow() {
+        assert!(parse_duration("18446744073709551615d").is_err());
+        assert!(parse_size("18446744073709551615G").is_err());
+        assert_eq!(parse_size("18446744073709551615"), Ok(u64::MAX));
+    }
+
+    #[test]
+    fn supervision_or() {
+        let call = Supervision {
+            timeout: Some(Duration::from_secs(5)),
+            ..Supervision::default()
+        };
+        let declared = Supervision {
+            timeout: Some(Duration::from_secs(60)),
+            memory: Some(1024),
+            ..Supervision::default()
+        };
+
+        assert_eq!(
+            call.or(&declared),
+            Supervision {
+                timeout: Some(Duration::from_secs(5)),
+                memory: Some(1024),
+                ..Supervision::default()
+            }
+        );
+    }
+}
@@ -0,0 +1,55 @@
+use std::env;
+use std::fs::{self, DirBuilder};
+use std::io::ErrorKind;
+use std::os::unix::fs::DirBuilderExt;
+use std::ops::Deref;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+static DIRS: AtomicUsize = AtomicUsize::new(0);
+
+/// An empty directory for a test, removed when it is dropped, also when the test fails.
+///
+/// The name holds the pid and a counter, so that tests running in parallel, in this
+/// binary or in another one, never share a directory. It is created with mode 0700.
+pub struct TempDir(PathBuf);
+
+impl TempDir {
+    pub fn new(name: &str) -> TempDir {
+        loop {
+            let path = env::temp_dir().join(format!(
+                "irnix-{name}-test-{}-{}",
+                std::process::id(),
+                DIRS.fetch_add(1, Ordering::Relaxed)
+            ));
+
+            // The temporary directory is shared, a path that already exists, left over or
+            // created by someone else, is skipped rather than reused.
+            match DirBuilder::new().mode(0o700).create(&path) {
+                Ok(()) => return TempDir(path),
+                Err(err) if err.kind() == ErrorKind::AlreadyExists => continue,
+                Err(err) => panic!("{path:?}: {err}"),
+            }
+        }
+    }
+}
+
+impl Deref for TempDir {
+    type Target = Path;
+
+    fn deref(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl AsRef<Path> for TempDir {
+    fn as_ref(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
@@ -0,0 +1,109 @@
+use std::collections::HashSet;
+use std::ffi::CString;
+use std::fs;
+use std::io;
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
+
+const MASK: u32 = libc::IN_CREATE
+    | libc::IN_DELETE
+    | libc::IN_MOVED_FROM
+    | libc::IN_MOVED_TO
+    | libc::IN_CLOSE_WRITE
+    | libc::IN_ATTRIB
+    | libc::IN_DELETE_SELF;
+
+/// Reports changes anywhere in a namespace through inotify.
+pub struct Watcher {
+    fd: OwnedFd,
+}
+
+impl Watcher {
+    pub fn new() -> io::Result<Watcher> {
+        let fd = unsafe { libc::inotify_init1(libc::IN_CLOEXEC) };
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        Ok(Watcher {
+            fd: unsafe { OwnedFd::from_raw_fd(fd) },
+        })
+    }
+
+    /// Watches `root` and every directory below it, following symlinks to objects.
+    /// Watching a directory twice is harmless, so this is called again after every change
+    /// to pick up new directories.
+    pub fn watch_tree(&self, root: &Path) -> io::Result<()> {
+        let mut visited = HashSet::new();
+        self.watch_dir(root.to_path_buf(), &mut visited)
+    }
+
+    fn watch_dir(&self, dir: PathBuf, visited: &mut HashSet<PathBuf>) -> io::Result<()> {
+        if !visited.insert(fs::canonicalize(&dir)?) {
+            return Ok(());
+        }
+
+        let path = CString::new(dir.as_os_str().as_bytes()).map_err(io::Error::other)?;
+        if unsafe { libc::inotify_add_watch(self.fd.as_raw_fd(), path.as_ptr(), MASK) } < 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        for entry in fs::read_dir(&dir)? {
+            let path = entry?.path();
+            if path.is_dir() {
+                self.watch_dir(path, visited)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Blocks until something changes and consumes the pending events.
+    pub fn wait(&self) -> io::Result<()> {
+        let mut buffer = [0u8; 4096];
+
+        loop {
+            let read = unsafe {
+                libc::read(
+                    self.fd.as_raw_fd(),
+                    buffer.as_mut_ptr() as *mut libc::c_void,
+                    buffer.len(),
+                )
+            };
+
+            if read > 0 {
+                return Ok(());
+            }
+
+            let err = io::Error::last_os_error();
+            if err.kind() != io::ErrorKind::Interrupted {
+                return Err(err);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::temp_dir::TempDir;
+    use std::sync::mpsc;
+    use std::thread;
+    use std::time::Duration;
+
+    #[test]
+    fn change() {
+        let root = TempDir::new("watch");
+        fs::create_dir_all(root.join("net/wifi")).unwrap();
+
+        let watcher = Watcher::new().unwrap();
+        watcher.watch_tree(&root).unwrap();
+
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || sender.send(watcher.wait().is_ok()));
+
+        fs::write(root.join("net/wifi/status"), "").unwrap();
+        assert_eq!(receiver.recv_timeout(Duration::from_secs(5)), Ok(true));
+    }
+}